@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::{
+    input::{gamepad::{GamepadConnection, GamepadConnectionEvent}, InputSystem},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+// This plugin turns keyboard and gamepad input into the actions gameplay systems read,
+// so keys can be rebound in the menu without touching the systems themselves
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<KeyBindings>()
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem))
+            .add_systems(FixedUpdate, clear_fixed_presses.after(ReadsFixedPresses))
+            .add_systems(Update, log_gamepad_connections);
+    }
+}
+
+// Fixed update systems that read `ActionState::fixed_just_pressed` go in this set, the
+// presses are cleared once they had their tick
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ReadsFixedPresses;
+
+// how far a stick has to be pushed before it counts
+const STICK_DEADZONE: f32 = 0.2;
+// how far a stick has to be pushed to count as a direction button
+const STICK_PRESS: f32 = 0.5;
+
+// Everything the player can do with a key
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Attack,
+    DrinkPotion,
+    Fire,
+    Interact,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Attack,
+        Action::DrinkPotion,
+        Action::Fire,
+        Action::Interact,
+        Action::Pause,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move Up",
+            Action::MoveDown => "Move Down",
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::Attack => "Attack",
+            Action::DrinkPotion => "Drink Potion",
+            Action::Fire => "Fire",
+            Action::Interact => "Interact",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+// Ready made sets of bindings that only differ in the movement keys
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Preset {
+    Wasd,
+    Arrows,
+    Vi,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Wasd, Preset::Arrows, Preset::Vi];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Preset::Wasd => "WASD",
+            Preset::Arrows => "Arrows",
+            Preset::Vi => "Vi",
+        }
+    }
+
+    // keys for up, down, left and right
+    fn movement_keys(self) -> [KeyCode; 4] {
+        match self {
+            Preset::Wasd => [KeyCode::W, KeyCode::S, KeyCode::A, KeyCode::D],
+            Preset::Arrows => [KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right],
+            Preset::Vi => [KeyCode::K, KeyCode::J, KeyCode::H, KeyCode::L],
+        }
+    }
+}
+
+// Which key triggers each action, saved with the other settings
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyBindings(BTreeMap<Action, KeyCode>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::preset(Preset::Wasd)
+    }
+}
+
+impl KeyBindings {
+    pub fn preset(preset: Preset) -> Self {
+        let [up, down, left, right] = preset.movement_keys();
+        KeyBindings(BTreeMap::from([
+            (Action::MoveUp, up),
+            (Action::MoveDown, down),
+            (Action::MoveLeft, left),
+            (Action::MoveRight, right),
+            (Action::Attack, KeyCode::Space),
+            (Action::DrinkPotion, KeyCode::Q),
+            (Action::Fire, KeyCode::F),
+            (Action::Interact, KeyCode::E),
+            (Action::Pause, KeyCode::Escape),
+        ]))
+    }
+
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.0.get(&action).copied()
+    }
+
+    // A key only ever drives one action, the action that had it before gets the
+    // rebound action's old key instead
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        let previous = self.0.insert(action, key);
+        for (other, other_key) in self.0.iter_mut() {
+            if *other != action && *other_key == key {
+                match previous {
+                    Some(previous) => *other_key = previous,
+                    None => warn!("{other:?} lost its key to {action:?}"),
+                }
+            }
+        }
+    }
+
+    // Actions missing from a settings file written by an older version keep their default key
+    pub fn with_defaults(mut self) -> Self {
+        for (action, key) in KeyBindings::default().0 {
+            if !self.0.contains_key(&action) && !self.0.values().any(|bound| *bound == key) {
+                self.0.insert(action, key);
+            }
+        }
+        self
+    }
+}
+
+// Moving through the buttons of a menu with the keyboard or a gamepad, these don't
+// depend on the key bindings
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MenuInput {
+    Previous,
+    Next,
+    Confirm,
+    Back,
+}
+
+// Keys that move through menus, Tab goes backwards with shift held
+fn key_menu_input(key: KeyCode, shift: bool) -> Option<MenuInput> {
+    match key {
+        KeyCode::Up | KeyCode::Left => Some(MenuInput::Previous),
+        KeyCode::Down | KeyCode::Right => Some(MenuInput::Next),
+        KeyCode::Tab if shift => Some(MenuInput::Previous),
+        KeyCode::Tab => Some(MenuInput::Next),
+        KeyCode::Return | KeyCode::NumpadEnter => Some(MenuInput::Confirm),
+        KeyCode::Back => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+// Gamepad buttons of the actions, gamepads can't be rebound
+fn gamepad_button_action(button: GamepadButtonType) -> Option<Action> {
+    match button {
+        GamepadButtonType::DPadUp => Some(Action::MoveUp),
+        GamepadButtonType::DPadDown => Some(Action::MoveDown),
+        GamepadButtonType::DPadLeft => Some(Action::MoveLeft),
+        GamepadButtonType::DPadRight => Some(Action::MoveRight),
+        GamepadButtonType::South => Some(Action::Attack),
+        GamepadButtonType::West => Some(Action::DrinkPotion),
+        GamepadButtonType::North => Some(Action::Fire),
+        GamepadButtonType::East => Some(Action::Interact),
+        GamepadButtonType::Start => Some(Action::Pause),
+        _ => None,
+    }
+}
+
+fn gamepad_button_menu_input(button: GamepadButtonType) -> Option<MenuInput> {
+    match button {
+        GamepadButtonType::DPadUp | GamepadButtonType::DPadLeft => Some(MenuInput::Previous),
+        GamepadButtonType::DPadDown | GamepadButtonType::DPadRight => Some(MenuInput::Next),
+        GamepadButtonType::South => Some(MenuInput::Confirm),
+        GamepadButtonType::East => Some(MenuInput::Back),
+        _ => None,
+    }
+}
+
+// Actions and menu inputs held down and started this frame, read by gameplay and menu
+// systems instead of the keyboard or gamepads
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    // analog movement from a stick, zero when the stick is at rest
+    stick: Vec2,
+    menu_pressed: HashSet<MenuInput>,
+    menu_just_pressed: HashSet<MenuInput>,
+    // presses kept until the next fixed tick, a frame can run none or several of them
+    fixed_just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    // Like `just_pressed` for systems in `FixedUpdate`, true on exactly one fixed tick
+    // per press
+    pub fn fixed_just_pressed(&self, action: Action) -> bool {
+        self.fixed_just_pressed.contains(&action)
+    }
+
+    pub fn menu_just_pressed(&self, input: MenuInput) -> bool {
+        self.menu_just_pressed.contains(&input)
+    }
+
+    // Direction to move in, x to the right and y up. Sticks move as far as they are
+    // pushed, keys and the D-pad at full speed along each axis.
+    pub fn movement(&self) -> Vec2 {
+        if self.stick != Vec2::ZERO {
+            return self.stick;
+        }
+        let axis = |positive, negative| {
+            (self.pressed(positive) as i32 - self.pressed(negative) as i32) as f32
+        };
+        Vec2::new(
+            axis(Action::MoveRight, Action::MoveLeft),
+            axis(Action::MoveUp, Action::MoveDown),
+        )
+    }
+}
+
+// Gamepads are looked up every frame, so one plugged in mid game works right away
+pub fn update_action_state(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    time: Res<Time<Virtual>>,
+    mut action_state: ResMut<ActionState>,
+) {
+    let mut pressed = HashSet::new();
+    let mut menu_pressed = HashSet::new();
+    let mut stick = Vec2::ZERO;
+
+    for action in Action::ALL {
+        if bindings.key(action).is_some_and(|key| keyboard_input.pressed(key)) {
+            pressed.insert(action);
+        }
+    }
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for key in keyboard_input.get_pressed() {
+        menu_pressed.extend(key_menu_input(*key, shift));
+    }
+
+    for gamepad in gamepads.iter() {
+        for button in gamepad_buttons.get_pressed().filter(|button| button.gamepad == gamepad) {
+            pressed.extend(gamepad_button_action(button.button_type));
+            menu_pressed.extend(gamepad_button_menu_input(button.button_type));
+        }
+
+        let axis = |axis_type| {
+            gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        let left_stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        if left_stick.length() > STICK_DEADZONE {
+            stick += left_stick;
+        }
+        // pushing the stick far enough also works like the D-pad
+        for (held, action, menu_input) in [
+            (left_stick.y > STICK_PRESS, Action::MoveUp, MenuInput::Previous),
+            (left_stick.y < -STICK_PRESS, Action::MoveDown, MenuInput::Next),
+            (left_stick.x < -STICK_PRESS, Action::MoveLeft, MenuInput::Previous),
+            (left_stick.x > STICK_PRESS, Action::MoveRight, MenuInput::Next),
+        ] {
+            if held {
+                pressed.insert(action);
+                menu_pressed.insert(menu_input);
+            }
+        }
+    }
+
+    action_state.just_pressed = pressed.difference(&action_state.pressed).copied().collect();
+    action_state.pressed = pressed;
+    // no fixed ticks run while paused, presses in the pause menu aren't for the game
+    if time.is_paused() {
+        action_state.fixed_just_pressed.clear();
+    } else {
+        let just_pressed = action_state.just_pressed.clone();
+        action_state.fixed_just_pressed.extend(just_pressed);
+    }
+    action_state.menu_just_pressed = menu_pressed
+        .difference(&action_state.menu_pressed)
+        .copied()
+        .collect();
+    action_state.menu_pressed = menu_pressed;
+    action_state.stick = stick.clamp_length_max(1.0);
+}
+
+fn clear_fixed_presses(mut action_state: ResMut<ActionState>) {
+    action_state.fixed_just_pressed.clear();
+}
+
+fn log_gamepad_connections(mut connection_events: EventReader<GamepadConnectionEvent>) {
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => info!("Gamepad {} connected: {}", event.gamepad.id, info.name),
+            GamepadConnection::Disconnected => info!("Gamepad {} disconnected", event.gamepad.id),
+        }
+    }
+}
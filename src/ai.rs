@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enemy::{in_attack_range, Enemy},
+    gamestate::in_game,
+    health::Health,
+    map::TileMap,
+    pathfinding::{find_player_location, move_pathfinders, Pathinder},
+    player::Player,
+};
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .register_type::<AiState>()
+            .init_resource::<AiDebug>()
+            .add_systems(
+                FixedUpdate,
+                update_ai_state.before(move_pathfinders).run_if(in_game),
+            )
+            .add_systems(
+                Update, (
+                toggle_ai_debug,
+                add_state_labels,
+                update_state_labels,
+            ).run_if(in_game));
+    }
+}
+
+const LABEL_FONT_SIZE: f32 = 18.0;
+// an enemy that hasn't made it back to its post by then stays where it is
+const RETURN_TIMEOUT_SECONDS: f32 = 15.0;
+
+// What an enemy is currently busy with, decided every tick by `update_ai_state`
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default, Reflect, Serialize, Deserialize)]
+pub enum AiState {
+    // walking the level's patrol route, or standing at its post when the level has none
+    #[default]
+    Patrol,
+    // player in sight, closing in on them
+    Chase,
+    // player in reach, hitting them
+    Attack,
+    // hurt too badly or too cowardly to fight, running from the player
+    Flee,
+    // lost sight of the player, checking where they were last seen
+    Investigate,
+    // gave up on the player, walking back to its post
+    Return,
+}
+
+// Where an enemy stands guard and how far along the patrol route it is
+#[derive(Component, Debug)]
+pub struct Guard {
+    pub post: IVec2,
+    next_waypoint: Option<usize>,
+    // time left to get back to the post while returning
+    returning: Timer,
+}
+
+impl Guard {
+    pub fn new(post: IVec2) -> Self {
+        Guard {
+            post,
+            next_waypoint: None,
+            returning: Timer::from_seconds(RETURN_TIMEOUT_SECONDS, TimerMode::Once),
+        }
+    }
+
+    // Gives up on getting back to the post, the enemy guards the cell it is on instead
+    pub fn stay_at(&mut self, cell: IVec2) {
+        self.post = cell;
+    }
+
+    // Waypoint to walk to next, moving on once the current one is reached. Patrols
+    // start at the waypoint closest to the post so enemies spread along the route.
+    pub fn next_waypoint(&mut self, route: &PatrolRoute, cell: IVec2) -> Option<IVec2> {
+        if route.0.is_empty() {
+            return None;
+        }
+        let post = self.post;
+        let mut next = self.next_waypoint.unwrap_or_else(|| {
+            (0..route.0.len())
+                .min_by_key(|index| {
+                    let offset = (route.0[*index] - post).abs();
+                    offset.x + offset.y
+                })
+                .unwrap()
+        });
+        if route.0[next] == cell {
+            next = (next + 1) % route.0.len();
+        }
+        self.next_waypoint = Some(next);
+        Some(route.0[next])
+    }
+}
+
+// Waypoints of the current level in the order they are walked, from the digits
+// `1` to `9` in the level file. There is one route per level, shared by every enemy.
+#[derive(Resource, Default, Debug)]
+pub struct PatrolRoute(pub Vec<IVec2>);
+
+// Shows the state of every enemy above its head, toggled with F3
+#[derive(Resource, Default)]
+struct AiDebug(bool);
+
+#[derive(Component)]
+struct AiStateLabel;
+
+fn update_ai_state(
+    time: Res<Time>,
+    tile_map: Res<TileMap>,
+    player_query: Query<&Transform, With<Player>>,
+    mut enemy_query: Query<(Entity, &Transform, &Enemy, &Health, &mut Pathinder, &mut Guard, &mut AiState), Without<Player>>,
+) {
+    let player_transform = player_query.single();
+    let player_cell = TileMap::world_to_grid(player_transform.translation);
+    for (entity, transform, enemy, health, mut pathfinder, mut guard, mut state) in enemy_query.iter_mut() {
+        let cell = TileMap::world_to_grid(transform.translation);
+        if *state == AiState::Return && guard.returning.tick(time.delta()).just_finished() {
+            guard.stay_at(cell);
+        }
+
+        let sees_player = find_player_location(
+            &tile_map,
+            player_transform.translation,
+            transform.translation,
+            pathfinder.vision,
+        );
+
+        let next_state = if sees_player {
+            pathfinder.last_seen = Some(player_cell);
+            if health.health <= enemy.kind.flee_health() {
+                AiState::Flee
+            } else if in_attack_range(transform.translation, player_transform.translation) {
+                AiState::Attack
+            } else {
+                AiState::Chase
+            }
+        } else {
+            match *state {
+                AiState::Chase | AiState::Attack if pathfinder.last_seen.is_some() => AiState::Investigate,
+                AiState::Chase | AiState::Attack | AiState::Flee => AiState::Return,
+                // the pathfinder forgets the last seen cell once it got there
+                AiState::Investigate if pathfinder.last_seen.is_none() => AiState::Return,
+                AiState::Return if cell == guard.post => AiState::Patrol,
+                state => state,
+            }
+        };
+
+        if next_state != *state {
+            debug!("{entity:?} {:?} -> {next_state:?}", *state);
+            if next_state == AiState::Return {
+                guard.returning.reset();
+            }
+            *state = next_state;
+        }
+    }
+}
+
+fn toggle_ai_debug(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut ai_debug: ResMut<AiDebug>,
+    mut label_query: Query<&mut Visibility, With<AiStateLabel>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        ai_debug.0 = !ai_debug.0;
+        for mut visibility in label_query.iter_mut() {
+            *visibility = if ai_debug.0 { Visibility::Inherited } else { Visibility::Hidden };
+        }
+    }
+}
+
+fn add_state_labels(
+    mut commands: Commands,
+    ai_debug: Res<AiDebug>,
+    enemy_query: Query<(Entity, &AiState), Added<AiState>>,
+) {
+    for (entity, state) in enemy_query.iter() {
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn(Text2dBundle {
+                    text: Text::from_section(
+                        format!("{state:?}"),
+                        TextStyle {
+                            font_size: LABEL_FONT_SIZE,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    ),
+                    transform: Transform::from_translation(Vec3::new(0.0, 35.0, 10.0)),
+                    visibility: if ai_debug.0 { Visibility::Inherited } else { Visibility::Hidden },
+                    ..default()
+                })
+                .insert(AiStateLabel);
+        });
+    }
+}
+
+fn update_state_labels(
+    enemy_query: Query<(&AiState, &Children), Changed<AiState>>,
+    mut label_query: Query<&mut Text, With<AiStateLabel>>,
+) {
+    for (state, children) in enemy_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = label_query.get_mut(*child) {
+                text.sections[0].value = format!("{state:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    ascii::AsciiSheet,
+    enemy::{Enemy, ENEMY_SIZE},
+    gamestate::in_game,
+    health::{apply_damage, DeathEvent},
+    item::spawn_item,
+    player::Player,
+    settings::DisplayQuality,
+    summary::RunStats,
+};
+
+// This plugin handles what an enemy leaves behind when it dies: a short burst where
+// it fell, its corpse, the loot it drops and the experience the player gets for it.
+// Removing the enemy itself and the player's death are up to `health_check`.
+pub struct DeathPlugin;
+
+impl Plugin for DeathPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(FixedUpdate, enemy_death.after(apply_damage).run_if(in_game))
+            .add_systems(Update, animate_death_effects.run_if(in_game));
+    }
+}
+
+// glyphs the death effect goes through, from the hit to the dust settling
+const DEATH_EFFECT_GLYPHS: [char; 4] = ['*', '+', '\'', '.'];
+const DEATH_EFFECT_SECONDS: f32 = 0.4;
+// corpses lie under items and enemies
+const CORPSE_Z: f32 = 400.0;
+const DEATH_EFFECT_Z: f32 = 890.0;
+
+// What is left of a dead enemy, stays until the level is left
+#[derive(Component)]
+pub struct Corpse;
+
+#[derive(Component)]
+pub struct DeathEffect {
+    timer: Timer,
+    color: Color,
+}
+
+fn enemy_death(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    display_quality: Res<DisplayQuality>,
+    mut run_stats: ResMut<RunStats>,
+    mut death_events: EventReader<DeathEvent>,
+    enemy_query: Query<(&Transform, &Enemy)>,
+    player_query: Query<(), With<Player>>,
+) {
+    let mut rng = rand::thread_rng();
+    for death in death_events.read() {
+        let Ok((transform, enemy)) = enemy_query.get(death.entity) else {
+            continue;
+        };
+        let position = transform.translation;
+
+        if display_quality.effects() {
+            let mut sprite = TextureAtlasSprite::new(DEATH_EFFECT_GLYPHS[0] as usize);
+            sprite.color = enemy.kind.color();
+            sprite.custom_size = Some(Vec2::splat(ENEMY_SIZE));
+            commands.spawn(SpriteSheetBundle {
+                sprite,
+                texture_atlas: ascii.0.clone(),
+                transform: Transform::from_translation(position.truncate().extend(DEATH_EFFECT_Z)),
+                ..default()
+            })
+            .insert(DeathEffect {
+                timer: Timer::from_seconds(DEATH_EFFECT_SECONDS, TimerMode::Once),
+                color: enemy.kind.color(),
+            })
+            .insert(Name::new("Death Effect"));
+        }
+
+        if let Some((glyph, color)) = enemy.kind.corpse() {
+            let mut sprite = TextureAtlasSprite::new(glyph as usize);
+            sprite.color = color;
+            sprite.custom_size = Some(Vec2::splat(ENEMY_SIZE));
+            commands.spawn(SpriteSheetBundle {
+                sprite,
+                texture_atlas: ascii.0.clone(),
+                transform: Transform::from_translation(position.truncate().extend(CORPSE_Z)),
+                ..default()
+            })
+            .insert(Corpse)
+            .insert(Name::new("Corpse"));
+        }
+
+        for (item, chance) in enemy.kind.loot() {
+            if rng.gen_bool(*chance) {
+                spawn_item(&mut commands, &ascii, *item, position.truncate().extend(500.0));
+            }
+        }
+
+        // only the player's kills count towards the run
+        if death.killer.is_some_and(|killer| player_query.contains(killer)) {
+            run_stats.kills += 1;
+            run_stats.experience += enemy.kind.experience();
+            debug!("Experience: {}", run_stats.experience);
+        }
+    }
+}
+
+// steps through the glyphs while fading out, then goes away
+fn animate_death_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut effect_query: Query<(Entity, &mut DeathEffect, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut effect, mut sprite) in effect_query.iter_mut() {
+        effect.timer.tick(time.delta());
+        if effect.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = effect.timer.percent();
+        let frame = (progress * DEATH_EFFECT_GLYPHS.len() as f32) as usize;
+        sprite.index = DEATH_EFFECT_GLYPHS[frame.min(DEATH_EFFECT_GLYPHS.len() - 1)] as usize;
+        sprite.color = effect.color.with_a(1.0 - progress);
+    }
+}
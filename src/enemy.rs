@@ -1,9 +1,15 @@
 use bevy::{prelude::*, sprite::collide_aabb::collide};
+use serde::{Deserialize, Serialize};
 use crate::{ascii::AsciiSheet, 
-    health::Health, 
-    pathfinding::Pathinder, 
-    player::{Player, PLAYER_SIZE},
-    gamestate::GameState, map::GameLevel,
+    health::{DamageEvent, DamageKind, Health}, 
+    item::Item,
+    projectile::spawn_projectile,
+    stun::Stunned,
+    pathfinding::{Pathinder, PathPlan}, 
+    player::{knock_back, Player, PLAYER_SIZE},
+    gamestate::in_game,
+    ai::{AiState, Guard},
+    map::TileMap,
 };
 
 pub struct EnemyPlugin;
@@ -14,29 +20,131 @@ impl Plugin for EnemyPlugin {
             .add_systems(
                 FixedUpdate, (
                 attack_player,
-            ).run_if(in_state(GameState::Game(GameLevel::Level1))));
+                shoot_player,
+            ).run_if(in_game));
     }
 }
 
 pub const ENEMY_SIZE: f32 = 50.0;
 const ENEMY_SPEED: f32 = 40.0;
+const ENEMY_PROJECTILE_SPEED: f32 = 250.0;
 
 #[derive(Component)]
 pub struct Enemy {
+    pub kind: EnemyKind,
     timer: Timer,
 }
 
+// The kinds of enemies a level can be populated with
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EnemyKind {
+    Grunt,
+    // weak and runs away from the player
+    Coward,
+    // shoots at the player from afar
+    Archer,
+}
+
+impl EnemyKind {
+    // Letter used for the enemy in level files
+    pub fn from_glyph(glyph: char) -> Option<EnemyKind> {
+        match glyph {
+            'g' => Some(EnemyKind::Grunt),
+            'c' => Some(EnemyKind::Coward),
+            'a' => Some(EnemyKind::Archer),
+            _ => None,
+        }
+    }
+
+    fn health(self) -> i32 {
+        match self {
+            EnemyKind::Grunt => 30,
+            EnemyKind::Coward => 10,
+            EnemyKind::Archer => 15,
+        }
+    }
+
+    // the enemy runs away once its health drops to this
+    pub fn flee_health(self) -> i32 {
+        match self {
+            EnemyKind::Grunt => 5,
+            EnemyKind::Coward => i32::MAX,
+            EnemyKind::Archer => 3,
+        }
+    }
+
+    fn vision(self) -> f32 {
+        match self {
+            EnemyKind::Grunt => 250.0,
+            EnemyKind::Coward => 300.0,
+            EnemyKind::Archer => 350.0,
+        }
+    }
+
+    fn speed(self) -> f32 {
+        match self {
+            EnemyKind::Grunt => ENEMY_SPEED,
+            EnemyKind::Coward => ENEMY_SPEED * 1.5,
+            EnemyKind::Archer => ENEMY_SPEED * 0.8,
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            EnemyKind::Grunt => Color::rgb(1.0, 0.1, 0.1),
+            EnemyKind::Coward => Color::rgb(1.0, 0.9, 0.2),
+            EnemyKind::Archer => Color::rgb(0.3, 0.9, 0.3),
+        }
+    }
+
+    // glyph and colour of what's left after it dies, cowards don't leave anything
+    pub fn corpse(self) -> Option<(char, Color)> {
+        match self {
+            EnemyKind::Grunt => Some(('%', Color::rgb(0.45, 0.1, 0.1))),
+            EnemyKind::Coward => None,
+            EnemyKind::Archer => Some(('%', Color::rgb(0.1, 0.4, 0.1))),
+        }
+    }
+
+    // items it may drop on death, each rolled on its own with the given chance
+    pub fn loot(self) -> &'static [(Item, f64)] {
+        match self {
+            EnemyKind::Grunt => &[(Item::Gold, 0.5), (Item::Potion, 0.2)],
+            EnemyKind::Coward => &[(Item::Gold, 0.8)],
+            EnemyKind::Archer => &[(Item::Ammo, 0.6)],
+        }
+    }
+
+    // experience the player gets for killing it
+    pub fn experience(self) -> u32 {
+        match self {
+            EnemyKind::Grunt => 10,
+            EnemyKind::Coward => 4,
+            EnemyKind::Archer => 8,
+        }
+    }
+
+    // damage of its projectiles, none for enemies that only fight up close
+    fn ranged_damage(self) -> Option<i32> {
+        match self {
+            EnemyKind::Archer => Some(1),
+            EnemyKind::Grunt | EnemyKind::Coward => None,
+        }
+    }
+}
+
 pub fn spawn_enemy(
     commands: &mut Commands,
     ascii: &Res<AsciiSheet>,
+    kind: EnemyKind,
     spawn_point: Vec3,
-) {
+) -> Entity {
     let mut background_sprite = TextureAtlasSprite::new(0);
     background_sprite.color = Color::rgb(0.2, 0.2, 0.2);
     background_sprite.custom_size = Some(Vec2::splat(ENEMY_SIZE));  
 
     let mut sprite = TextureAtlasSprite::new(1);
-    sprite.color = Color::rgb(1.0, 0.1, 0.1);
+    sprite.color = kind.color();
     sprite.custom_size = Some(Vec2::splat(ENEMY_SIZE));
 
     commands.spawn(SpriteSheetBundle {
@@ -46,16 +154,21 @@ pub fn spawn_enemy(
         ..default()
     })
     .insert(Enemy{
+        kind,
         timer: Timer::from_seconds(1.5, TimerMode::Repeating)
     })
     .insert(Name::new("Enemy"))
     .insert(Health {
-        health: 30,
+        health: kind.health(),
     })
     .insert(Pathinder {
-        vision: 250.0,
-        speed: ENEMY_SPEED,
+        vision: kind.vision(),
+        speed: kind.speed(),
+        last_seen: None,
     })
+    .insert(PathPlan::default())
+    .insert(AiState::default())
+    .insert(Guard::new(TileMap::world_to_grid(spawn_point)))
     .with_children(|parent| {
         parent
         .spawn(SpriteSheetBundle {
@@ -65,27 +178,80 @@ pub fn spawn_enemy(
             ..default()
         })
         .insert(Name::new("Background"));
-    });
+    })
+    .id()
 }
 
 
-// if within range, attack player every 2 seconds
+pub fn in_attack_range(
+    enemy_position: Vec3,
+    player_position: Vec3,
+) -> bool {
+    collide(
+        enemy_position,
+        Vec2::splat(ENEMY_SIZE * 1.2),
+        player_position,
+        Vec2::splat(PLAYER_SIZE),
+    )
+    .is_some()
+}
+
+// while in the attack state, attack player every 1.5 seconds and knock them back
+// like the player does to enemies. Stunned enemies don't attack.
 pub fn attack_player(
-    mut enemy_transform: Query<(&Transform, &mut Enemy)>,
-    mut player_query: Query<(&Transform, &mut Health), With<Player>>,
+    mut enemy_transform: Query<(Entity, &Transform, &mut Enemy, &AiState), (Without<Player>, Without<Stunned>)>,
+    mut player_query: Query<(Entity, &mut Transform), With<Player>>,
+    time: Res<Time>,
+    tile_map: Res<TileMap>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let (player_entity, mut player_transform) = player_query.single_mut();
+    for (enemy_entity, enemy_transform, mut enemy, state) in enemy_transform.iter_mut() {
+        if *state == AiState::Attack
+            && in_attack_range(enemy_transform.translation, player_transform.translation)
+            && enemy.timer.tick(time.delta()).just_finished()
+        {
+            damage_events.send(DamageEvent {
+                source: Some(enemy_entity),
+                target: player_entity,
+                amount: 1,
+                kind: DamageKind::Melee,
+            });
+            let direction = (player_transform.translation - enemy_transform.translation)
+                .truncate()
+                .normalize_or_zero();
+            knock_back(direction, &tile_map, player_transform.reborrow());
+        }
+    }
+}
+
+// enemies with a ranged attack shoot at the player they are chasing, on the same
+// timer as their melee attacks
+pub fn shoot_player(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    mut enemy_query: Query<(Entity, &Transform, &mut Enemy, &AiState), Without<Stunned>>,
+    player_query: Query<&Transform, With<Player>>,
     time: Res<Time>,
 ) {
-    let (player_transform, mut health) = player_query.single_mut();
-    for (enemy_transform, mut enemy) in enemy_transform.iter_mut() {
-        let collision = collide(
-            enemy_transform.translation,
-            Vec2::splat(ENEMY_SIZE * 1.2),
-            player_transform.translation,
-            Vec2::splat(PLAYER_SIZE),
-        );
-        if collision.is_some() && enemy.timer.tick(time.delta()).just_finished(){
-            health.health -= 1;
-            println!("Player Health: {}", health.health);
+    let player_transform = player_query.single();
+    for (enemy_entity, enemy_transform, mut enemy, state) in enemy_query.iter_mut() {
+        let Some(damage) = enemy.kind.ranged_damage() else {
+            continue;
+        };
+        if *state == AiState::Chase && enemy.timer.tick(time.delta()).just_finished() {
+            let direction = (player_transform.translation - enemy_transform.translation)
+                .truncate()
+                .normalize_or_zero();
+            spawn_projectile(
+                &mut commands,
+                &ascii,
+                enemy_entity,
+                enemy_transform.translation,
+                direction * ENEMY_PROJECTILE_SPEED,
+                damage,
+                enemy.kind.color(),
+            );
         }
     }
 }
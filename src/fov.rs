@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    death::Corpse,
+    enemy::Enemy,
+    gamestate::in_game,
+    item::Item,
+    map::{Tile, TileMap},
+    player::Player,
+    projectile::Projectile,
+    settings::DisplayQuality,
+};
+
+pub struct FovPlugin;
+
+impl Plugin for FovPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Fov>()
+            .add_systems(Update, (update_fov, dim_tiles, hide_unseen_entities).chain().run_if(in_game));
+    }
+}
+
+// how many tiles the player can see in every direction
+const FOV_RADIUS: i32 = 8;
+// brightness of tiles that were seen before but are out of sight now
+const REMEMBERED_BRIGHTNESS: f32 = 0.35;
+// brightness at the edge of the view when lighting effects are on, fading from full at the player
+const EDGE_BRIGHTNESS: f32 = 0.6;
+
+// Transforms for shadowcasting, each one maps the first octant onto another
+const OCTANTS: [[i32; 4]; 8] = [
+    [1, 0, 0, 1],
+    [0, 1, 1, 0],
+    [0, -1, 1, 0],
+    [-1, 0, 0, 1],
+    [-1, 0, 0, -1],
+    [0, -1, -1, 0],
+    [0, 1, -1, 0],
+    [1, 0, 0, -1],
+];
+
+// What the player can see right now and what they have seen so far on this level
+#[derive(Resource, Default)]
+pub struct Fov {
+    origin: Option<IVec2>,
+    width: i32,
+    visible: Vec<bool>,
+    explored: Vec<bool>,
+    // tiles whose colour is still out of date, closest to the player first
+    pending: VecDeque<Entity>,
+}
+
+impl Fov {
+    pub fn new(width: i32, height: i32) -> Self {
+        Fov {
+            origin: None,
+            width,
+            visible: vec![false; (width * height) as usize],
+            explored: vec![false; (width * height) as usize],
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let inside = cell.x >= 0 && cell.y >= 0 && cell.x < self.width;
+        let index = (cell.y * self.width + cell.x) as usize;
+        (inside && index < self.visible.len()).then_some(index)
+    }
+
+    pub fn is_visible(&self, cell: IVec2) -> bool {
+        self.index(cell).is_some_and(|index| self.visible[index])
+    }
+
+    pub fn is_explored(&self, cell: IVec2) -> bool {
+        self.index(cell).is_some_and(|index| self.explored[index])
+    }
+
+    // Explored cells row by row, for save files
+    pub fn explored(&self) -> &[bool] {
+        &self.explored
+    }
+
+    // Remembers cells explored before, a save from a differently sized map is ignored
+    pub fn restore_explored(&mut self, explored: Vec<bool>) {
+        if explored.len() == self.explored.len() {
+            self.explored = explored;
+            self.origin = None;
+        }
+    }
+
+    fn reveal(&mut self, cell: IVec2) {
+        if let Some(index) = self.index(cell) {
+            self.visible[index] = true;
+            self.explored[index] = true;
+        }
+    }
+}
+
+fn update_fov(
+    tile_map: Res<TileMap>,
+    display_quality: Res<DisplayQuality>,
+    mut fov: ResMut<Fov>,
+    player_query: Query<&Transform, With<Player>>,
+    tile_query: Query<(Entity, &Transform), With<Tile>>,
+) {
+    let player_cell = TileMap::world_to_grid(player_query.single().translation);
+    // opening a door changes the map and may reveal what is behind it, and lighting
+    // effects can be turned on or off in the settings
+    if fov.origin == Some(player_cell) && !tile_map.is_changed() && !display_quality.is_changed() {
+        return;
+    }
+
+    fov.origin = Some(player_cell);
+    fov.visible.iter_mut().for_each(|visible| *visible = false);
+    fov.reveal(player_cell);
+    for octant in OCTANTS {
+        cast_light(&tile_map, &mut fov, player_cell, 1, 1.0, 0.0, octant);
+    }
+
+    let mut tiles: Vec<(Entity, i32)> = tile_query
+        .iter()
+        .map(|(entity, transform)| {
+            let offset = TileMap::world_to_grid(transform.translation) - player_cell;
+            (entity, offset.x * offset.x + offset.y * offset.y)
+        })
+        .collect();
+    tiles.sort_by_key(|(_, distance)| *distance);
+    fov.pending = tiles.into_iter().map(|(entity, _)| entity).collect();
+}
+
+// Recolours the tiles the last view change left out of date, as many per frame as the
+// display quality allows. Tiles spawn unexplored, tiles in sight get the colour
+// `spawn_map` gave them and remembered ones are dimmed.
+fn dim_tiles(
+    display_quality: Res<DisplayQuality>,
+    mut fov: ResMut<Fov>,
+    mut tile_query: Query<(&Transform, &Tile, &mut TextureAtlasSprite)>,
+) {
+    let Some(origin) = fov.origin else {
+        return;
+    };
+    for _ in 0..display_quality.fov_tiles_per_frame() {
+        let Some(entity) = fov.pending.pop_front() else {
+            return;
+        };
+        let Ok((transform, tile, mut sprite)) = tile_query.get_mut(entity) else {
+            continue;
+        };
+        let cell = TileMap::world_to_grid(transform.translation);
+        sprite.color = if fov.is_visible(cell) && display_quality.effects() {
+            // the player carries a torch, light falls off towards the edge of the view
+            let distance = (cell - origin).as_vec2().length() / FOV_RADIUS as f32;
+            dim(tile.color, 1.0 - (1.0 - EDGE_BRIGHTNESS) * distance.min(1.0))
+        } else if fov.is_visible(cell) {
+            tile.color
+        } else if fov.is_explored(cell) {
+            dim(tile.color, REMEMBERED_BRIGHTNESS)
+        } else {
+            Color::NONE
+        };
+    }
+}
+
+fn dim(color: Color, brightness: f32) -> Color {
+    let [r, g, b, a] = color.as_rgba_f32();
+    Color::rgba(r * brightness, g * brightness, b * brightness, a)
+}
+
+// Recursive shadowcasting over one octant, rows further from the origin are scanned
+// between the `start` and `end` slopes that are not yet in shadow
+fn cast_light(
+    tile_map: &TileMap,
+    fov: &mut Fov,
+    origin: IVec2,
+    row: i32,
+    mut start: f32,
+    end: f32,
+    [xx, xy, yx, yy]: [i32; 4],
+) {
+    if start < end {
+        return;
+    }
+    let mut new_start = 0.0;
+    for distance in row..=FOV_RADIUS {
+        let dy = -distance;
+        let mut blocked = false;
+        for dx in -distance..=0 {
+            let cell = origin + IVec2::new(dx * xx + dy * xy, dx * yx + dy * yy);
+            let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+            if start < right_slope {
+                continue;
+            } else if end > left_slope {
+                break;
+            }
+
+            if dx * dx + dy * dy <= FOV_RADIUS * FOV_RADIUS {
+                fov.reveal(cell);
+            }
+
+            let opaque = tile_map.get(cell).blocks_sight();
+            if blocked {
+                if opaque {
+                    new_start = right_slope;
+                } else {
+                    blocked = false;
+                    start = new_start;
+                }
+            } else if opaque && distance < FOV_RADIUS {
+                // everything behind this wall is in shadow, scan the lit part past it first
+                blocked = true;
+                cast_light(tile_map, fov, origin, distance + 1, start, left_slope, [xx, xy, yx, yy]);
+                new_start = right_slope;
+            }
+        }
+        if blocked {
+            break;
+        }
+    }
+}
+
+// enemies and projectiles can only be seen while in view, items stay visible once discovered
+fn hide_unseen_entities(
+    fov: Res<Fov>,
+    mut in_view_query: Query<(&Transform, &mut Visibility), Or<(With<Enemy>, With<Projectile>)>>,
+    mut item_query: Query<(&Transform, &mut Visibility), (Or<(With<Item>, With<Corpse>)>, Without<Enemy>, Without<Projectile>)>,
+) {
+    for (transform, mut visibility) in in_view_query.iter_mut() {
+        let cell = TileMap::world_to_grid(transform.translation);
+        *visibility = if fov.is_visible(cell) { Visibility::Inherited } else { Visibility::Hidden };
+    }
+    for (transform, mut visibility) in item_query.iter_mut() {
+        let cell = TileMap::world_to_grid(transform.translation);
+        *visibility = if fov.is_explored(cell) { Visibility::Inherited } else { Visibility::Hidden };
+    }
+}
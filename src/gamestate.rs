@@ -7,6 +7,12 @@ pub enum GameState {
     Splash,
     Menu,
     Game(GameLevel),
+    GameOver,
+    Victory,
+}
+// Run condition for systems that should run in every level of the game
+pub fn in_game(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Game(_))
 }
 // Generic system that takes a component as a parameter, and will despawn all entities with that component
 pub fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
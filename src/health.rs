@@ -1,15 +1,29 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 
-use crate::{gamestate::GameState, map::GameLevel};
+use crate::{
+    enemy::Enemy,
+    gamestate::{in_game, GameState},
+    menu::TEXT_COLOR,
+    player::{Player, PLAYER_MAX_HEALTH},
+    sound::SoundEffect,
+    summary::RunStats,
+};
 
+// This plugin owns all changes to `Health` from damage. Attackers send a `DamageEvent`,
+// `apply_damage` takes it off the target's health and sends a `DeathEvent` once it
+// drops to zero, which `health_check` then handles. What a dead enemy leaves behind
+// is up to the `DeathPlugin`. The player's health is shown in the corner of the screen.
 pub struct HealthPlugin;
 
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
         app
+            .add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
             .add_systems(FixedUpdate,
-                health_check.run_if(in_state(GameState::Game(GameLevel::Level1)))
-            );
+                (tick_invulnerability, apply_damage, health_check).chain().run_if(in_game)
+            )
+            .add_systems(Update, update_health_text.run_if(in_game));
     }
 }
 
@@ -18,15 +32,161 @@ pub struct Health {
     pub health: i32,
 }
 
-pub fn health_check(
+// Text showing the player's health, goes away with the level
+#[derive(Component)]
+pub struct HealthText;
+
+// What dealt the damage
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageKind {
+    Melee,
+    Ranged,
+    Trap,
+}
+
+// Sent by anything that hurts an entity with `Health`
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    // the attacker, none for the level itself like traps
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: DamageKind,
+}
+
+// Sent once when an entity's health drops to zero
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+}
+
+// how long the player can't be hurt again after taking damage
+const PLAYER_INVULNERABLE_SECONDS: f32 = 0.5;
+
+// Ignores all damage until the timer runs out
+#[derive(Component)]
+pub struct Invulnerable(Timer);
+
+impl Invulnerable {
+    pub fn new(seconds: f32) -> Self {
+        Invulnerable(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+fn tick_invulnerability(
     mut commands: Commands,
-    health_query: Query<(Entity, &Health)>,
+    time: Res<Time>,
+    mut invulnerable_query: Query<(Entity, &mut Invulnerable)>,
 ) {
-    for (entity, health) in health_query.iter() {
-        if health.health <= 0 {
-            commands.entity(entity).despawn_recursive();
+    for (entity, mut invulnerable) in invulnerable_query.iter_mut() {
+        if invulnerable.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
         }
     }
 }
 
+// Anything that changes damage before it is taken off, like armour, goes here next
+// to the invulnerability check
+pub fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut health_query: Query<(&mut Health, Has<Player>, Has<Enemy>, Has<Invulnerable>)>,
+    source_query: Query<(), With<Player>>,
+    mut run_stats: ResMut<RunStats>,
+    mut sound_effects: EventWriter<SoundEffect>,
+) {
+    // `Invulnerable` is only inserted once this tick is over, hits later in the same
+    // tick check this instead
+    let mut hurt_players = HashSet::new();
+    for damage in damage_events.read() {
+        let Ok((mut health, is_player, is_enemy, invulnerable)) = health_query.get_mut(damage.target) else {
+            continue;
+        };
+        // already dead and waiting to be despawned
+        if health.health <= 0 || invulnerable || hurt_players.contains(&damage.target) {
+            continue;
+        }
+        let amount = damage.amount.clamp(0, health.health);
+        health.health -= amount;
 
+        if damage.source.is_some_and(|source| source_query.contains(source)) {
+            run_stats.damage_dealt += amount;
+        }
+        if is_player {
+            sound_effects.send(SoundEffect::PlayerHurt);
+            debug!("Player Health: {} ({:?} damage)", health.health, damage.kind);
+            // a moment to get away, so a crowd of enemies doesn't take the player down at once
+            commands.entity(damage.target).insert(Invulnerable::new(PLAYER_INVULNERABLE_SECONDS));
+            hurt_players.insert(damage.target);
+        } else if is_enemy {
+            sound_effects.send(SoundEffect::EnemyHit);
+            debug!("Enemy Health: {} ({:?} damage)", health.health, damage.kind);
+        }
+
+        if health.health == 0 {
+            death_events.send(DeathEvent {
+                entity: damage.target,
+                killer: damage.source,
+            });
+        }
+    }
+}
+
+pub fn health_check(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut death_events: EventReader<DeathEvent>,
+    dead_query: Query<Has<Player>>,
+    mut sound_effects: EventWriter<SoundEffect>,
+) {
+    for death in death_events.read() {
+        let Ok(is_player) = dead_query.get(death.entity) else {
+            continue;
+        };
+        sound_effects.send(SoundEffect::Death);
+        if is_player {
+            // the level is torn down when leaving the game, every system expects a player until then
+            game_state.set(GameState::GameOver);
+        } else {
+            commands.entity(death.entity).despawn_recursive();
+        }
+    }
+}
+
+fn update_health_text(
+    mut commands: Commands,
+    player_query: Query<&Health, With<Player>>,
+    mut text_query: Query<&mut Text, With<HealthText>>,
+) {
+    let Ok(health) = player_query.get_single() else {
+        return;
+    };
+    let value = format!("Health: {}/{PLAYER_MAX_HEALTH}", health.health);
+    if let Ok(mut text) = text_query.get_single_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        return;
+    }
+    // above the ammo, see `update_ammo_text`
+    commands.spawn(
+        TextBundle::from_section(
+            value,
+            TextStyle {
+                font_size: 30.0,
+                color: TEXT_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+    )
+    .insert(HealthText)
+    .insert(Name::new("Health Text"));
+}
@@ -0,0 +1,121 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, ActionState, ReadsFixedPresses},
+    ascii::AsciiSheet,
+    gamestate::in_game,
+    health::Health,
+    player::{Player, PLAYER_MAX_HEALTH, PLAYER_SIZE},
+    projectile::Ammo,
+};
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(
+                FixedUpdate, (
+                pick_up_items,
+                drink_potion.in_set(ReadsFixedPresses),
+            ).run_if(in_game));
+    }
+}
+
+const ITEM_SIZE: f32 = 50.0;
+const POTION_HEAL: i32 = 25;
+// shots in one ammo pickup
+const AMMO_PICKUP: u32 = 5;
+
+// Things lying on the floor of a level that the player can pick up
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Item {
+    Gold,
+    Potion,
+    Ammo,
+}
+
+impl Item {
+    // Glyph used for the item in level files
+    pub fn from_glyph(glyph: char) -> Option<Item> {
+        match glyph {
+            '$' => Some(Item::Gold),
+            '!' => Some(Item::Potion),
+            ')' => Some(Item::Ammo),
+            _ => None,
+        }
+    }
+
+    fn sprite(self) -> TextureAtlasSprite {
+        let (index, color) = match self {
+            Item::Gold => ('$' as usize, Color::rgb(1.0, 0.85, 0.0)),
+            Item::Potion => ('!' as usize, Color::rgb(0.9, 0.2, 0.9)),
+            Item::Ammo => (')' as usize, Color::rgb(0.8, 0.8, 1.0)),
+        };
+        let mut sprite = TextureAtlasSprite::new(index);
+        sprite.color = color;
+        sprite.custom_size = Some(Vec2::splat(ITEM_SIZE));
+        sprite
+    }
+}
+
+// What the player has picked up so far
+#[derive(Component, Default, Debug)]
+pub struct Inventory {
+    pub gold: u32,
+    pub potions: u32,
+}
+
+pub fn spawn_item(
+    commands: &mut Commands,
+    ascii: &Res<AsciiSheet>,
+    item: Item,
+    spawn_point: Vec3,
+) {
+    commands.spawn(SpriteSheetBundle {
+        sprite: item.sprite(),
+        texture_atlas: ascii.0.clone(),
+        transform: Transform::from_translation(spawn_point),
+        ..default()
+    })
+    .insert(item)
+    .insert(Name::new("Item"));
+}
+
+fn pick_up_items(
+    mut commands: Commands,
+    mut player_query: Query<(&Transform, &mut Inventory, &mut Ammo), With<Player>>,
+    item_query: Query<(Entity, &Transform, &Item)>,
+) {
+    let (player_transform, mut inventory, mut ammo) = player_query.single_mut();
+    for (entity, item_transform, item) in item_query.iter() {
+        let collision = collide(
+            player_transform.translation,
+            Vec2::splat(PLAYER_SIZE * 0.9),
+            item_transform.translation,
+            Vec2::splat(ITEM_SIZE * 0.5),
+        );
+        if collision.is_some() {
+            match item {
+                Item::Gold => inventory.gold += 1,
+                Item::Potion => inventory.potions += 1,
+                Item::Ammo => ammo.0 += AMMO_PICKUP,
+            }
+            debug!("Inventory: {:?}, {:?}", *inventory, *ammo);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn drink_potion(
+    action_state: Res<ActionState>,
+    mut player_query: Query<(&mut Inventory, &mut Health), With<Player>>,
+) {
+    let (mut inventory, mut health) = player_query.single_mut();
+    if action_state.fixed_just_pressed(Action::DrinkPotion) && inventory.potions > 0 {
+        inventory.potions -= 1;
+        health.health = (health.health + POTION_HEAL).min(PLAYER_MAX_HEALTH);
+        debug!("Player Health: {}", health.health);
+    }
+}
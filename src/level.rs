@@ -0,0 +1,204 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{enemy::EnemyKind, gamestate::GameState, map::{GameLevel, EXIT_GLYPHS}};
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(PreStartup, load_level_manifest);
+    }
+}
+
+const LEVEL_MANIFEST_PATH: &str = "assets/levels.ron";
+
+// Layout of `assets/levels.ron`, levels point at each other by id
+#[derive(Deserialize)]
+struct LevelManifestFile {
+    start: String,
+    levels: Vec<LevelEntry>,
+    #[serde(default)]
+    final_depth: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct LevelEntry {
+    id: String,
+    name: String,
+    map: String,
+    #[serde(default)]
+    music: Option<String>,
+    #[serde(default)]
+    enemies: Vec<EnemySpawn>,
+    #[serde(default)]
+    next: Option<String>,
+    #[serde(default)]
+    exits: HashMap<char, ExitEntry>,
+}
+
+// Where an exit glyph of the map leads, `entry` is the uppercase entry point to arrive at
+#[derive(Deserialize)]
+struct ExitEntry {
+    to: ExitTarget,
+    #[serde(default)]
+    entry: Option<char>,
+}
+
+#[derive(Deserialize)]
+enum ExitTarget {
+    Level(String),
+    // the first generated level below the hand made ones
+    Descend,
+    // ends the run
+    Victory,
+}
+
+// An enemy placed on a tile of the level, in map file columns and rows, on top of
+// the ones drawn into the map file itself
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct EnemySpawn {
+    pub kind: EnemyKind,
+    pub tile: (usize, usize),
+}
+
+// Everything needed to build one level, ids are already resolved to `GameLevel`s
+#[derive(Debug)]
+pub struct LevelInfo {
+    // what the manifest calls the level, stays the same when levels are added or reordered
+    pub id: String,
+    pub name: String,
+    pub map: String,
+    pub music: Option<String>,
+    pub enemies: Vec<EnemySpawn>,
+    // level the `%` exit leads to unless `exits` says otherwise, `None` descends into generated levels
+    pub next: Option<GameLevel>,
+    pub exits: HashMap<char, Exit>,
+}
+
+// Destination of an exit tile, another level or the end of the run
+#[derive(Clone, Copy, Debug)]
+pub struct Exit {
+    pub to: GameState,
+    pub entry: Option<char>,
+}
+
+// All levels of the game, read once at startup so designers can add levels without code changes
+#[derive(Resource, Debug)]
+pub struct LevelManifest {
+    pub start: GameLevel,
+    levels: Vec<LevelInfo>,
+    // generated level whose exit wins the game, `None` descends forever
+    final_depth: Option<u32>,
+}
+
+impl LevelManifest {
+    // Generated levels are not part of the manifest
+    pub fn get(&self, level: GameLevel) -> Option<&LevelInfo> {
+        match level {
+            GameLevel::Level(index) => self.levels.get(index),
+            GameLevel::Generated(_) => None,
+        }
+    }
+
+    // Manifest id of a hand made level, for save files
+    pub fn id(&self, level: GameLevel) -> Option<&str> {
+        self.get(level).map(|info| info.id.as_str())
+    }
+
+    // The hand made level with the given manifest id
+    pub fn find(&self, id: &str) -> Option<GameLevel> {
+        self.levels.iter().position(|info| info.id == id).map(GameLevel::Level)
+    }
+
+    // How deep a level is, generated levels are counted below the hand made ones
+    pub fn depth(&self, level: GameLevel) -> u32 {
+        match level {
+            GameLevel::Level(index) => index as u32 + 1,
+            GameLevel::Generated(depth) => self.levels.len() as u32 + depth,
+        }
+    }
+
+    // Where the exit with the given glyph leads. Exits missing from the manifest go
+    // to the next level, and once the hand made levels run out the player descends
+    // until the exit of the final depth.
+    pub fn exit(&self, level: GameLevel, glyph: char) -> Exit {
+        let to = |level| Exit {
+            to: GameState::Game(level),
+            entry: None,
+        };
+        match level {
+            GameLevel::Level(_) => {
+                let info = self.get(level).expect("Level missing from the manifest");
+                info.exits
+                    .get(&glyph)
+                    .copied()
+                    .unwrap_or_else(|| to(info.next.unwrap_or(GameLevel::Generated(1))))
+            }
+            GameLevel::Generated(depth) if self.final_depth.is_some_and(|last| depth >= last) => Exit {
+                to: GameState::Victory,
+                entry: None,
+            },
+            GameLevel::Generated(depth) => to(GameLevel::Generated(depth + 1)),
+        }
+    }
+}
+
+fn load_level_manifest(mut commands: Commands) {
+    commands.insert_resource(read_level_manifest());
+}
+
+pub fn read_level_manifest() -> LevelManifest {
+    let contents = fs::read_to_string(LEVEL_MANIFEST_PATH).expect("No level manifest found");
+    let file: LevelManifestFile = ron::from_str(&contents)
+        .unwrap_or_else(|error| panic!("Invalid level manifest {LEVEL_MANIFEST_PATH}: {error}"));
+
+    // levels are addressed by their position in the manifest once loaded
+    let resolve = |id: &str| -> GameLevel {
+        let index = file
+            .levels
+            .iter()
+            .position(|level| level.id == id)
+            .unwrap_or_else(|| panic!("Level manifest refers to unknown level `{id}`"));
+        GameLevel::Level(index)
+    };
+
+    let levels = file
+        .levels
+        .iter()
+        .map(|level| LevelInfo {
+            id: level.id.clone(),
+            name: level.name.clone(),
+            map: level.map.clone(),
+            music: level.music.clone(),
+            enemies: level.enemies.clone(),
+            next: level.next.as_deref().map(resolve),
+            exits: level
+                .exits
+                .iter()
+                .map(|(glyph, exit)| {
+                    assert!(
+                        EXIT_GLYPHS.contains(glyph),
+                        "Level `{}` has an exit for `{glyph}`, which is not an exit glyph",
+                        level.id
+                    );
+                    let destination = match &exit.to {
+                        ExitTarget::Level(id) => GameState::Game(resolve(id)),
+                        ExitTarget::Descend => GameState::Game(GameLevel::Generated(1)),
+                        ExitTarget::Victory => GameState::Victory,
+                    };
+                    (*glyph, Exit { to: destination, entry: exit.entry })
+                })
+                .collect(),
+        })
+        .collect();
+
+    LevelManifest {
+        start: resolve(&file.start),
+        levels,
+        final_depth: file.final_depth,
+    }
+}
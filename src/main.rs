@@ -1,4 +1,7 @@
-use bevy::{prelude::*, core_pipeline::clear_color::ClearColorConfig, app::AppExit};
+// bevy system parameters are long query types by nature, and systems take as many as they need
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+use bevy::{prelude::*, core_pipeline::clear_color::ClearColorConfig};
 
 mod player;
 mod ascii;
@@ -9,6 +12,19 @@ mod pathfinding;
 mod gamestate;
 mod splash;
 mod menu;
+mod level;
+mod item;
+mod ai;
+mod fov;
+mod summary;
+mod pause;
+mod save;
+mod settings;
+mod sound;
+mod actions;
+mod death;
+mod projectile;
+mod stun;
 
 use player::*;
 use ascii::*;
@@ -19,20 +35,51 @@ use pathfinding::*;
 use gamestate::*;
 use splash::*;
 use menu::*;
+use level::*;
+use item::*;
+use ai::*;
+use fov::*;
+use summary::*;
+use pause::*;
+use save::*;
+use settings::*;
+use sound::*;
+use actions::*;
+use death::*;
+use projectile::*;
+use stun::*;
 
 fn main() {
     App::new()                                                         
         .add_plugins((
             DefaultPlugins.set(ImagePlugin::default_nearest()),
             StartupPlugin,
+            LevelPlugin,
+            AsciiPlugin,
+            SavePlugin,
+            SettingsPlugin,
+            SoundPlugin,
+            ActionsPlugin,
+        ))
+        // screens around the game itself
+        .add_plugins((
             SplashPlugin,
             MenuPlugin,
-            AsciiPlugin,
+            PausePlugin,
+            SummaryPlugin,
+        ))
+        .add_plugins((
             MapPlugin,
             PlayerPlugin,
             EnemyPlugin,
             HealthPlugin,
+            DeathPlugin,
+            ProjectilePlugin,
+            StunPlugin,
             PathfinderPlugin,
+            ItemPlugin,
+            AiPlugin,
+            FovPlugin,
         ))
         .add_state::<GameState>()
         .run(); 
@@ -43,8 +90,7 @@ pub struct StartupPlugin;
 impl Plugin for StartupPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(Startup, spawn_camera)
-            .add_systems(Update, exit_app_on_esc);
+            .add_systems(Startup, spawn_camera);
     }
 }
 
@@ -57,15 +103,6 @@ fn spawn_camera(mut commands: Commands) {
     });
 }
 
-fn exit_app_on_esc(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut app_exit_events: ResMut<Events<AppExit>>,
-) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
-        app_exit_events.send(AppExit);
-    }
-} 
-
 
 
 
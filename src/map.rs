@@ -1,158 +1,509 @@
 
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use std::{collections::HashMap, env, fs};
 
-use bevy::{prelude::*, sprite::collide_aabb::collide};
-use crate::{ascii::*, player::{spawn_player, Player}, enemy::{spawn_enemy, Enemy}};
-use crate::gamestate::GameState;
+use bevy::{ecs::system::EntityCommands, prelude::*, sprite::collide_aabb::collide};
+use serde::{Deserialize, Serialize};
+use crate::{ascii::*, player::{spawn_player, Player, PLAYER_SIZE}, enemy::{spawn_enemy, Enemy, EnemyKind}};
+use crate::{health::{DamageEvent, DamageKind, HealthText}, item::{spawn_item, Item}};
+use crate::gamestate::{GameState, in_game};
+use crate::actions::{Action, ActionState, ReadsFixedPresses};
+use crate::level::LevelManifest;
+use crate::ai::PatrolRoute;
+use crate::fov::Fov;
+use crate::death::{Corpse, DeathEffect};
+use crate::projectile::{AmmoText, Projectile};
+use crate::summary::RunStats;
+use crate::sound::SoundEffect;
 
+pub mod generation;
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
         app
+            .insert_resource(RunSeed::from_env_or_random())
+            .init_resource::<TileMap>()
+            .init_resource::<PatrolRoute>()
+            .init_resource::<Entrance>()
+            .init_resource::<EntryPoints>()
+            // levels come from the manifest, so instead of one `OnEnter`/`OnExit` pair per
+            // level the world is rebuilt right after any change of `GameState`
             .add_systems(
-                OnEnter(GameState::Game(GameLevel::Level1)), 
-                load_level
-            )
-            .add_systems(OnExit(GameState::Game(GameLevel::Level1)), 
-            despawn_level
-            )
-            .add_systems(
-                OnEnter(GameState::Game(GameLevel::Level2)),
-                load_level
+                StateTransition,
+                (despawn_level, load_level)
+                    .chain()
+                    .after(apply_state_transition::<GameState>)
+                    .run_if(state_changed::<GameState>()),
             )
             .add_systems(
-                FixedUpdate,
-                exit_level.run_if(in_state(GameState::Game(GameLevel::Level1))),
-            );
+                FixedUpdate, (
+                exit_level.in_set(ReadsFixedPresses),
+                open_doors.in_set(ReadsFixedPresses),
+                trigger_traps,
+            ).run_if(in_game));
     }
 }
 
 const TILE_SIZE: f32 = 50.0;
+const FLOOR_GLYPH: usize = 176;
+const FLOOR_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+const DOOR_COLOR: Color = Color::rgb(0.6, 0.4, 0.2);
+const OPEN_DOOR_GLYPH: usize = '/' as usize;
+const TRAP_DAMAGE: i32 = 5;
 
+// A map tile, `color` is what it looks like while in the player's view
 #[derive(Component)]
-pub struct Tile;
+pub struct Tile {
+    pub color: Color,
+}
 
 #[derive(Component)]
 pub struct TileCollider;
 
+// Leads to another level, the glyph names the exit in the level manifest
+#[derive(Component)]
+pub struct ExitTile {
+    pub glyph: char,
+}
+
+// Glyphs drawn as exits in level files
+pub const EXIT_GLYPHS: [char; 3] = ['%', '<', '>'];
+
+// Entry point the player arrives at on the next level, `None` is the `@` start
+#[derive(Resource, Default, Debug)]
+pub struct Entrance(pub Option<char>);
+
+// Where the player can arrive on the current level, filled in by `spawn_map`
+#[derive(Resource, Default, Debug)]
+pub struct EntryPoints {
+    start: IVec2,
+    points: HashMap<char, IVec2>,
+}
+
+impl EntryPoints {
+    // The `@` start for `None` and entry points the level doesn't have
+    pub fn cell(&self, entrance: Option<char>) -> IVec2 {
+        entrance
+            .and_then(|entrance| self.points.get(&entrance).copied())
+            .unwrap_or(self.start)
+    }
+}
+
+// A closed door blocks like a wall until the player opens it
+#[derive(Component)]
+pub struct Door;
+
+// Hurts the player the first time they step on it
 #[derive(Component)]
-pub struct ExitTile;
+pub struct Trap {
+    armed: bool,
+}
+
+impl Trap {
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+}
 
-#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States, Resource)]
+// Identifies a level
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, Serialize, Deserialize)]
 pub enum GameLevel {
+    // hand made level, the index points into the `LevelManifest`
+    Level(usize),
+    // procedurally generated level at the given depth below the hand made ones
+    Generated(u32),
+}
+
+// Seed of the current run, all generated levels are built from it
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RunSeed(pub u64);
+
+impl RunSeed {
+    // `DELVER_SEED=<number>` replays a run, otherwise every run gets a fresh seed
+    pub fn from_env_or_random() -> Self {
+        let seed = env::var("DELVER_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random);
+        info!("Run seed: {seed}");
+        RunSeed(seed)
+    }
+}
+
+// What occupies a cell of the level grid
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TileKind {
     #[default]
-    Level1,
-    Level2,
-    Level3,
+    Wall,
+    Floor,
+    Exit,
+    // blocks like a wall until opened, then it is floor
+    Door,
+}
+
+impl TileKind {
+    pub fn is_walkable(self) -> bool {
+        matches!(self, TileKind::Floor | TileKind::Exit)
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        matches!(self, TileKind::Wall | TileKind::Door)
+    }
+}
+
+// The current level as a grid of cells, columns grow to the right and rows grow downwards.
+// Lets collision and pathing code look at the few cells around a position instead of
+// every tile entity.
+#[derive(Resource, Default, Debug)]
+pub struct TileMap {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<TileKind>,
+}
+
+impl TileMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        TileMap {
+            width,
+            height,
+            tiles: vec![TileKind::Wall; (width * height) as usize],
+        }
+    }
+
+    pub fn in_bounds(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    // anything outside of the map counts as wall
+    pub fn get(&self, cell: IVec2) -> TileKind {
+        if self.in_bounds(cell) {
+            self.tiles[(cell.y * self.width + cell.x) as usize]
+        } else {
+            TileKind::Wall
+        }
+    }
+
+    pub fn set(&mut self, cell: IVec2, kind: TileKind) {
+        if self.in_bounds(cell) {
+            self.tiles[(cell.y * self.width + cell.x) as usize] = kind;
+        }
+    }
+
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        self.get(cell).is_walkable()
+    }
+
+    // True when no wall or closed door stands on the straight line between the cells,
+    // walked with Bresenham's algorithm. The end cells themselves are not checked.
+    pub fn line_of_sight(&self, from: IVec2, to: IVec2) -> bool {
+        let delta = (to - from).abs();
+        let step = (to - from).signum();
+        let mut error = delta.x - delta.y;
+        let mut cell = from;
+        while cell != to {
+            if cell != from && self.get(cell).blocks_sight() {
+                return false;
+            }
+            let doubled = error * 2;
+            if doubled > -delta.y {
+                error -= delta.y;
+                cell.x += step.x;
+            }
+            if doubled < delta.x {
+                error += delta.x;
+                cell.y += step.y;
+            }
+        }
+        true
+    }
+
+    // Cell whose tile covers the world position
+    pub fn world_to_grid(position: Vec3) -> IVec2 {
+        IVec2::new(
+            (position.x / TILE_SIZE).round() as i32,
+            (-position.y / TILE_SIZE).round() as i32,
+        )
+    }
+
+    // Center of the cell in world space, at the given depth
+    pub fn grid_to_world(cell: IVec2, z: f32) -> Vec3 {
+        Vec3::new(cell.x as f32 * TILE_SIZE, -(cell.y as f32) * TILE_SIZE, z)
+    }
+
+    // True when a square of the given size centered on the position overlaps a cell that
+    // cannot be walked on. Only the cells under the square are looked at.
+    pub fn is_blocked(&self, position: Vec3, size: f32) -> bool {
+        let half = size / 2.0;
+        // a cell spans half a tile around its center, overlaps must be strict like `collide`
+        let first = |low: f32| (low / TILE_SIZE - 0.5).floor() as i32 + 1;
+        let last = |high: f32| (high / TILE_SIZE + 0.5).ceil() as i32 - 1;
+        for y in first(-position.y - half)..=last(-position.y + half) {
+            for x in first(position.x - half)..=last(position.x + half) {
+                if !self.is_walkable(IVec2::new(x, y)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
 }
 
 pub fn load_level(
     level: Res<State<GameState>>,
+    manifest: Res<LevelManifest>,
+    run_seed: Res<RunSeed>,
+    entrance: Res<Entrance>,
+    mut run_stats: ResMut<RunStats>,
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
 ) {
-    match level.get() {
-        GameState::Game(GameLevel::Level1) => {
-            let file = File::open("assets/level_1.txt").expect("No file found");
-            let player_spawn_point = Vec3::new(450.0, -250.0, 890.0);
-            let enemy_spawn_point = Vec3::new(600.0, -50.0, 880.0);
-            spawn_map(&mut commands, &ascii, file);
-            spawn_player(&mut commands, &ascii, player_spawn_point);
-            spawn_enemy(&mut commands, &ascii, enemy_spawn_point);
-        }
-        GameState::Game(GameLevel::Level2) => {
-            let file = File::open("assets/level_2.txt").expect("No file found");
-            let player_spawn_point = Vec3::new(100.0, -100.0, 890.0);
-            spawn_map(&mut commands, &ascii, file);
-            spawn_player(&mut commands, &ascii, player_spawn_point);
+    let GameState::Game(level) = *level.get() else {
+        return;
+    };
+    run_stats.depth = run_stats.depth.max(manifest.depth(level));
+
+    match level {
+        GameLevel::Level(_) => {
+            let info = manifest.get(level).expect("Level missing from the manifest");
+            info!("Entering {}", info.name);
+
+            let map = fs::read_to_string(format!("assets/{}", info.map)).expect("No file found");
+            let lines: Vec<String> = map.lines().map(String::from).collect();
+            spawn_map(&mut commands, &ascii, &lines, entrance.0);
+            // the manifest can place extra enemies on top of the ones in the map file
+            for enemy in info.enemies.iter() {
+                let cell = IVec2::new(enemy.tile.0 as i32, enemy.tile.1 as i32);
+                spawn_enemy(&mut commands, &ascii, enemy.kind, TileMap::grid_to_world(cell, 880.0));
+            }
         }
-        GameState::Game(GameLevel::Level3) => {
-            let file = File::open("assets/level_3.txt").expect("No file found");
+        GameLevel::Generated(depth) => {
+            info!("Descending to depth {depth}");
+            let seed = generation::level_seed(run_seed.0, depth);
+            spawn_map(&mut commands, &ascii, &generation::generate_level(seed, depth), None);
         }
-        _ => {}
     }
 }
 
+// Builds the tiles of a level file and everything placed on them. Legend:
+// - `#` wall, `%` `<` `>` exits, `+` door, `^` hidden trap, anything else is floor
+// - `@` player start, uppercase letters are entry points other levels' exits lead to
+// - lowercase letters are enemies, see `EnemyKind::from_glyph`
+// - `$`, `!` and `)` are items, see `Item::from_glyph`
+// - digits are the waypoints of the patrol route, walked from `1` up to `9`. A level
+//   has only the one route, every enemy on it patrols the same waypoints
 pub fn spawn_map(
     commands: &mut Commands,
     ascii: &Res<AsciiSheet>,
-    file: File,
+    lines: &[String],
+    entrance: Option<char>,
 ) {
-    let mut tiles= Vec::new();
-
-    for (y, line) in BufReader::new(file).lines().enumerate() {
-        if let Ok(line) = line {
-            for (x, char) in line.chars().enumerate() {
-                let tile = match char {
-                    '#' => {
-                        let mut sprite = TextureAtlasSprite::new(char as usize);
-                        sprite.color = Color::rgb(1.0, 1.0, 1.0);
-                        sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
-
-                        commands.spawn(SpriteSheetBundle {
-                            sprite,
-                            texture_atlas: ascii.0.clone(),
-                            transform: Transform::from_translation(Vec3::new(x as f32 * TILE_SIZE, -(y as f32 )* TILE_SIZE, 100.0)),
-                            ..default()
-                        })
+    let mut player_start = None;
+    let mut entry_points = HashMap::new();
+    let mut waypoints = Vec::new();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let mut tile_map = TileMap::new(width as i32, lines.len() as i32);
+
+    for (y, line) in lines.iter().enumerate() {
+        for (x, char) in line.chars().enumerate() {
+            let cell = IVec2::new(x as i32, y as i32);
+            let position = TileMap::grid_to_world(cell, 100.0);
+            tile_map.set(cell, match char {
+                '#' => TileKind::Wall,
+                '%' | '<' | '>' => TileKind::Exit,
+                '+' => TileKind::Door,
+                _ => TileKind::Floor,
+            });
+            match char {
+                '#' => {
+                    spawn_tile(commands, ascii, char as usize, Color::rgb(1.0, 1.0, 1.0), position)
+                        .insert(TileCollider);
+                }
+                '%' => {
+                    spawn_tile(commands, ascii, 206, Color::rgb(1.0, 1.0, 1.0), position)
+                        .insert(ExitTile { glyph: char });
+                }
+                '<' | '>' => {
+                    spawn_tile(commands, ascii, char as usize, Color::rgb(1.0, 1.0, 1.0), position)
+                        .insert(ExitTile { glyph: char });
+                }
+                '+' => {
+                    spawn_tile(commands, ascii, char as usize, DOOR_COLOR, position)
                         .insert(TileCollider)
-                        .insert(Tile)
-                        .id()
-                    }
-                    '%' => {
-                        let mut sprite = TextureAtlasSprite::new(206);
-                        sprite.color = Color::rgb(1.0, 1.0, 1.0);
-                        sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
-
-                        commands.spawn(SpriteSheetBundle {
-                            sprite,
-                            texture_atlas: ascii.0.clone(),
-                            transform: Transform::from_translation(Vec3::new(x as f32 * TILE_SIZE, -(y as f32 )* TILE_SIZE, 100.0)),
-                            ..default()
-                        })
-                        .insert(ExitTile)
-                        .insert(Tile)
-                        .id()
+                        .insert(Door);
+                }
+                '^' => {
+                    // traps look like floor until someone steps on them
+                    spawn_tile(commands, ascii, FLOOR_GLYPH, FLOOR_COLOR, position)
+                        .insert(Trap { armed: true });
+                }
+                _ => {
+                    spawn_tile(commands, ascii, FLOOR_GLYPH, FLOOR_COLOR, position);
+                    if char == '@' {
+                        player_start = Some(cell);
+                    } else if char.is_ascii_uppercase() {
+                        entry_points.insert(char, cell);
+                    } else if let Some(kind) = EnemyKind::from_glyph(char) {
+                        spawn_enemy(commands, ascii, kind, TileMap::grid_to_world(cell, 880.0));
+                    } else if let Some(item) = Item::from_glyph(char) {
+                        spawn_item(commands, ascii, item, TileMap::grid_to_world(cell, 500.0));
+                    } else if let Some(order) = char.to_digit(10) {
+                        waypoints.push((order, cell));
                     }
-                    _ => {
-                        let mut sprite = TextureAtlasSprite::new(176);
-                        sprite.color = Color::rgb(0.5, 0.5, 0.5);
-                        sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
-
-                        commands.spawn(SpriteSheetBundle {
-                            sprite,
-                            texture_atlas: ascii.0.clone(),
-                            transform: Transform::from_translation(Vec3::new(x as f32 * TILE_SIZE, -(y as f32 )* TILE_SIZE, 100.0)),
-                            ..default()
-                            
-                        })
-                        .insert(Tile)
-                        .id()
-                    }
-                };
-                tiles.push(tile);
-            }
+                }
+            };
+        }
+    }
+
+    if let Some(entrance) = entrance.filter(|entrance| !entry_points.contains_key(entrance)) {
+        warn!("Level has no entry point `{entrance}`, starting at `@` instead");
+    }
+    let entry_points = EntryPoints {
+        start: player_start.expect("Level has no player start `@`"),
+        points: entry_points,
+    };
+    spawn_player(commands, ascii, TileMap::grid_to_world(entry_points.cell(entrance), 890.0));
+    commands.insert_resource(entry_points);
+    commands.insert_resource(Fov::new(tile_map.width, tile_map.height));
+    commands.insert_resource(tile_map);
+    waypoints.sort_by_key(|(order, _)| *order);
+    commands.insert_resource(PatrolRoute(waypoints.into_iter().map(|(_, cell)| cell).collect()));
+}
+
+fn spawn_tile<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    ascii: &Res<AsciiSheet>,
+    index: usize,
+    color: Color,
+    position: Vec3,
+) -> EntityCommands<'w, 's, 'a> {
+    // unexplored until the fov has had a look, see `dim_tiles`
+    let mut sprite = TextureAtlasSprite::new(index);
+    sprite.color = Color::NONE;
+    sprite.custom_size = Some(Vec2::splat(TILE_SIZE));
+
+    let mut tile = commands.spawn(SpriteSheetBundle {
+        sprite,
+        texture_atlas: ascii.0.clone(),
+        transform: Transform::from_translation(position),
+        ..default()
+    });
+    tile.insert(Tile { color });
+    tile
+}
+
+// doors next to the player open when they interact
+fn open_doors(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    mut tile_map: ResMut<TileMap>,
+    player_query: Query<&Transform, With<Player>>,
+    mut door_query: Query<(Entity, &Transform, &mut TextureAtlasSprite), (With<Door>, With<TileCollider>)>,
+) {
+    if !action_state.fixed_just_pressed(Action::Interact) {
+        return;
+    }
+    let player_transform = player_query.single();
+    for (entity, door_transform, mut sprite) in door_query.iter_mut() {
+        let collision = collide(
+            player_transform.translation,
+            Vec2::splat(PLAYER_SIZE * 1.2),
+            door_transform.translation,
+            Vec2::splat(TILE_SIZE),
+        );
+        if collision.is_some() {
+            open_door(&mut commands, &mut tile_map, entity, door_transform, &mut sprite);
+        }
+    }
+}
+
+// Opened doors stay open and can be walked through like floor
+pub fn open_door(
+    commands: &mut Commands,
+    tile_map: &mut TileMap,
+    entity: Entity,
+    transform: &Transform,
+    sprite: &mut TextureAtlasSprite,
+) {
+    sprite.index = OPEN_DOOR_GLYPH;
+    tile_map.set(TileMap::world_to_grid(transform.translation), TileKind::Floor);
+    commands.entity(entity).remove::<TileCollider>();
+}
+
+// A sprung trap shows itself and doesn't go off again
+pub fn spring_trap(trap: &mut Trap, tile: &mut Tile, sprite: &mut TextureAtlasSprite) {
+    trap.armed = false;
+    sprite.index = '^' as usize;
+    tile.color = Color::rgb(1.0, 0.2, 0.2);
+    // a trap out of sight, like one restored from a save, gets its colour from the fov
+    if sprite.color != Color::NONE {
+        sprite.color = tile.color;
+    }
+}
+
+fn trigger_traps(
+    player_query: Query<(Entity, &Transform), With<Player>>,
+    mut trap_query: Query<(&Transform, &mut Trap, &mut Tile, &mut TextureAtlasSprite)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let (player_entity, player_transform) = player_query.single();
+    for (trap_transform, mut trap, mut tile, mut sprite) in trap_query.iter_mut() {
+        let collision = collide(
+            player_transform.translation,
+            Vec2::splat(PLAYER_SIZE * 0.5),
+            trap_transform.translation,
+            Vec2::splat(TILE_SIZE * 0.5),
+        );
+        if trap.armed && collision.is_some() {
+            spring_trap(&mut trap, &mut tile, &mut sprite);
+            damage_events.send(DamageEvent {
+                source: None,
+                target: player_entity,
+                amount: TRAP_DAMAGE,
+                kind: DamageKind::Trap,
+            });
         }
     }
 }
 
+// the player takes an exit by interacting while standing on it
 fn exit_level(
-    player_transform: Query<&Transform, With<Player>>,
-    exit_tile_transform: Query<&Transform, With<ExitTile>>,
+    action_state: Res<ActionState>,
+    level: Res<State<GameState>>,
+    manifest: Res<LevelManifest>,
+    mut entrance: ResMut<Entrance>,
+    entry_points: Res<EntryPoints>,
+    mut player_transform: Query<&mut Transform, With<Player>>,
+    exit_tile_query: Query<(&Transform, &ExitTile), Without<Player>>,
     mut gamestate: ResMut<NextState<GameState>>,
+    mut sound_effects: EventWriter<SoundEffect>,
 ) {
-    let player_transform = player_transform.single();
-    let exit_tile_transform = exit_tile_transform.single();
-    if player_reached_exit_tile(
-        player_transform.translation, 
-        exit_tile_transform.translation) {
-            gamestate.set(GameState::Game(GameLevel::Level2));
-        }
+    if !action_state.fixed_just_pressed(Action::Interact) {
+        return;
+    }
+    let GameState::Game(level) = *level.get() else {
+        return;
+    };
+    let mut player_transform = player_transform.single_mut();
+    for (exit_tile_transform, exit_tile) in exit_tile_query.iter() {
+        if player_reached_exit_tile(
+            player_transform.translation,
+            exit_tile_transform.translation) {
+                let exit = manifest.exit(level, exit_tile.glyph);
+                entrance.0 = exit.entry;
+                sound_effects.send(SoundEffect::LevelExit);
+                if exit.to == GameState::Game(level) {
+                    // setting the state it is already in does nothing, so the player
+                    // is only moved to the entry point on this level
+                    let cell = entry_points.cell(exit.entry);
+                    player_transform.translation = TileMap::grid_to_world(cell, player_transform.translation.z);
+                } else {
+                    // the world is rebuilt from the new state, see `MapPlugin`
+                    gamestate.set(exit.to);
+                }
+                return;
+            }
+    }
 }
 
 fn player_reached_exit_tile (
@@ -166,10 +517,7 @@ fn player_reached_exit_tile (
         Vec2::splat(TILE_SIZE),
     );
 
-    if collision.is_some() {
-        return true;
-    }
-    false
+    collision.is_some()
 }
 
 fn despawn_level(
@@ -177,6 +525,11 @@ fn despawn_level(
     mut enemy_query: Query<Entity, With<Enemy>>,
     mut tile_query: Query<Entity, With<Tile>>,
     mut player_query: Query<Entity, With<Player>>,
+    mut item_query: Query<Entity, With<Item>>,
+    mut remains_query: Query<
+        Entity,
+        Or<(With<Corpse>, With<DeathEffect>, With<Projectile>, With<AmmoText>, With<HealthText>)>,
+    >,
 ) {
     for entity in enemy_query.iter_mut() {
         commands.entity(entity).despawn_recursive();
@@ -187,4 +540,10 @@ fn despawn_level(
     for entity in player_query.iter_mut() {
         commands.entity(entity).despawn_recursive();
     }
+    for entity in item_query.iter_mut() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in remains_query.iter_mut() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,504 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+// Procedural levels, written out in the same glyphs as the level files so they
+// go through `spawn_map` like any hand made level
+
+const MAP_WIDTH: usize = 60;
+const MAP_HEIGHT: usize = 40;
+const MIN_ROOM_SIZE: usize = 4;
+const MAX_ROOM_SIZE: usize = 10;
+// smallest area a BSP leaf can have and still fit a room with walls around it
+const MIN_LEAF_SIZE: usize = MIN_ROOM_SIZE + 4;
+const ROOM_PLACEMENT_ATTEMPTS: usize = 200;
+const MAX_PLACED_ROOMS: usize = 12;
+// chance of a tile starting out as wall before the caves are smoothed
+const CAVE_FILL_CHANCE: f64 = 0.45;
+const CAVE_SMOOTHING_PASSES: usize = 5;
+// caves whose largest open area is smaller than this share of the map are thrown away
+const MIN_CAVE_SHARE: f32 = 0.35;
+// enemies in caves never start closer to the player than this many steps
+const MIN_ENEMY_DISTANCE: u32 = 12;
+
+// Gives every depth of a run its own seed, so the same run seed always builds the same levels
+pub fn level_seed(run_seed: u64, depth: u32) -> u64 {
+    run_seed ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// Builds a generated level in one of the available styles, caves show up about
+// one level in three
+pub fn generate_level(seed: u64, depth: u32) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    if rng.gen_bool(1.0 / 3.0) {
+        generate_cave(rng.gen(), depth)
+    } else {
+        generate_rooms(rng.gen(), depth)
+    }
+}
+
+// The two ways of laying out rooms, every generated level picks one at random
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoomLayout {
+    // recursively split the map in two and put a room in every part
+    Bsp,
+    // drop rooms wherever they fit and chain them together
+    Scattered,
+}
+
+// Builds a rooms and corridors dungeon for the given depth, with the player start,
+// exit, enemies and items already placed
+pub fn generate_rooms(seed: u64, depth: u32) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let layout = if rng.gen_bool(0.5) { RoomLayout::Bsp } else { RoomLayout::Scattered };
+
+    let mut grid = Grid::new(MAP_WIDTH, MAP_HEIGHT, '#');
+    let rooms = match layout {
+        RoomLayout::Bsp => bsp_rooms(&mut grid, &mut rng),
+        RoomLayout::Scattered => scattered_rooms(&mut grid, &mut rng),
+    };
+    populate_rooms(&mut grid, &rooms, depth, &mut rng);
+    grid.to_lines()
+}
+
+// A rectangle of the map in columns and rows
+#[derive(Clone, Copy, Debug)]
+pub struct Room {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Room {
+    pub fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    // true when the rooms overlap or touch, leaving no wall between them
+    fn touches(&self, other: &Room) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+
+    fn random_tile(&self, rng: &mut StdRng) -> (usize, usize) {
+        (
+            rng.gen_range(self.x..self.x + self.width),
+            rng.gen_range(self.y..self.y + self.height),
+        )
+    }
+}
+
+// Map being generated, one glyph per tile
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<char>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, fill: char) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![fill; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> char {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, glyph: char) {
+        self.cells[y * self.width + x] = glyph;
+    }
+
+    pub fn to_lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.width)
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    fn is_border(&self, x: usize, y: usize) -> bool {
+        x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1
+    }
+
+    // walls among the 8 surrounding tiles, anything past the border counts as wall
+    fn walls_around(&self, x: usize, y: usize) -> usize {
+        let mut walls = 0;
+        for dy in -1..=1_i32 {
+            for dx in -1..=1_i32 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                let outside = nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32;
+                if outside || self.get(nx as usize, ny as usize) == '#' {
+                    walls += 1;
+                }
+            }
+        }
+        walls
+    }
+
+    // Walking distance from `start` to every tile reachable from it, `None` for walls
+    // and tiles that cannot be reached
+    fn distances_from(&self, start: (usize, usize)) -> Vec<Option<u32>> {
+        let mut distances = vec![None; self.width * self.height];
+        let mut queue = VecDeque::from([start]);
+        distances[start.1 * self.width + start.0] = Some(0);
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[y * self.width + x].unwrap();
+            for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                let index = ny * self.width + nx;
+                if self.get(nx, ny) != '#' && distances[index].is_none() {
+                    distances[index] = Some(distance + 1);
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        distances
+    }
+
+    fn carve_room(&mut self, room: &Room) {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                self.set(x, y, '.');
+            }
+        }
+    }
+
+    // L shaped corridor, horizontal first then vertical
+    fn carve_corridor(&mut self, from: (usize, usize), to: (usize, usize)) {
+        for x in from.0.min(to.0)..=from.0.max(to.0) {
+            self.set(x, from.1, '.');
+        }
+        for y in from.1.min(to.1)..=from.1.max(to.1) {
+            self.set(to.0, y, '.');
+        }
+    }
+}
+
+fn bsp_rooms(grid: &mut Grid, rng: &mut StdRng) -> Vec<Room> {
+    let mut rooms = Vec::new();
+    let whole_map = Room {
+        x: 1,
+        y: 1,
+        width: grid.width - 2,
+        height: grid.height - 2,
+    };
+    split_leaf(grid, whole_map, rng, &mut rooms);
+    rooms
+}
+
+// Splits the leaf until it is small enough for one room, then connects the rooms of
+// both halves. Returns a room of this leaf for the parent to connect to.
+fn split_leaf(grid: &mut Grid, leaf: Room, rng: &mut StdRng, rooms: &mut Vec<Room>) -> Room {
+    let can_split_wide = leaf.width >= MIN_LEAF_SIZE * 2;
+    let can_split_tall = leaf.height >= MIN_LEAF_SIZE * 2;
+    let too_big = leaf.width > MAX_ROOM_SIZE + 2 || leaf.height > MAX_ROOM_SIZE + 2;
+
+    if (can_split_wide || can_split_tall) && (too_big || rng.gen_bool(0.75)) {
+        // split across the longer side so leaves stay roughly square
+        let split_wide = if can_split_wide && can_split_tall {
+            leaf.width >= leaf.height
+        } else {
+            can_split_wide
+        };
+        let (first, second) = if split_wide {
+            let cut = rng.gen_range(MIN_LEAF_SIZE..=leaf.width - MIN_LEAF_SIZE);
+            (
+                Room { width: cut, ..leaf },
+                Room { x: leaf.x + cut, width: leaf.width - cut, ..leaf },
+            )
+        } else {
+            let cut = rng.gen_range(MIN_LEAF_SIZE..=leaf.height - MIN_LEAF_SIZE);
+            (
+                Room { height: cut, ..leaf },
+                Room { y: leaf.y + cut, height: leaf.height - cut, ..leaf },
+            )
+        };
+        let first_room = split_leaf(grid, first, rng, rooms);
+        let second_room = split_leaf(grid, second, rng, rooms);
+        grid.carve_corridor(first_room.center(), second_room.center());
+        return if rng.gen_bool(0.5) { first_room } else { second_room };
+    }
+
+    // leave at least a one tile wall on every side of the room
+    let width = rng.gen_range(MIN_ROOM_SIZE..=(leaf.width - 2).min(MAX_ROOM_SIZE));
+    let height = rng.gen_range(MIN_ROOM_SIZE..=(leaf.height - 2).min(MAX_ROOM_SIZE));
+    let room = Room {
+        x: leaf.x + rng.gen_range(1..=leaf.width - width - 1),
+        y: leaf.y + rng.gen_range(1..=leaf.height - height - 1),
+        width,
+        height,
+    };
+    grid.carve_room(&room);
+    rooms.push(room);
+    room
+}
+
+fn scattered_rooms(grid: &mut Grid, rng: &mut StdRng) -> Vec<Room> {
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..ROOM_PLACEMENT_ATTEMPTS {
+        if rooms.len() >= MAX_PLACED_ROOMS {
+            break;
+        }
+        let width = rng.gen_range(MIN_ROOM_SIZE..=MAX_ROOM_SIZE);
+        let height = rng.gen_range(MIN_ROOM_SIZE..=MAX_ROOM_SIZE);
+        let room = Room {
+            x: rng.gen_range(1..grid.width - width - 1),
+            y: rng.gen_range(1..grid.height - height - 1),
+            width,
+            height,
+        };
+        if rooms.iter().any(|other| room.touches(other)) {
+            continue;
+        }
+        grid.carve_room(&room);
+        if let Some(previous) = rooms.last() {
+            grid.carve_corridor(previous.center(), room.center());
+        }
+        rooms.push(room);
+    }
+    rooms
+}
+
+// Puts the player in the first room, the exit in the room furthest from it and
+// scatters enemies and items over the others, more enemies the deeper the level
+fn populate_rooms(grid: &mut Grid, rooms: &[Room], depth: u32, rng: &mut StdRng) {
+    let start = rooms[0].center();
+    grid.set(start.0, start.1, '@');
+
+    let exit_room = rooms
+        .iter()
+        .skip(1)
+        .max_by_key(|room| {
+            let (x, y) = room.center();
+            x.abs_diff(start.0) + y.abs_diff(start.1)
+        })
+        .unwrap_or(&rooms[0]);
+    let mut exit = exit_room.random_tile(rng);
+    while exit == start {
+        exit = exit_room.random_tile(rng);
+    }
+    grid.set(exit.0, exit.1, '%');
+
+    let other_rooms: Vec<&Room> = rooms.iter().skip(1).collect();
+    if other_rooms.is_empty() {
+        return;
+    }
+    let mut glyphs = vec!['g'; 2 + depth as usize];
+    // archers start showing up from the second generated level on
+    glyphs.extend(vec!['a'; depth as usize / 2]);
+    for _ in 0..rng.gen_range(1..=3) {
+        glyphs.push(*['$', '!', ')'].choose(rng).unwrap());
+    }
+    for glyph in glyphs {
+        let room = other_rooms.choose(rng).unwrap();
+        let (x, y) = room.random_tile(rng);
+        if grid.get(x, y) == '.' {
+            grid.set(x, y, glyph);
+        }
+    }
+}
+
+// Builds an organic cave: random noise smoothed by a cellular automaton, keeping
+// only the largest connected open area so every tile of it can be walked to
+pub fn generate_cave(seed: u64, depth: u32) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    loop {
+        let mut grid = random_fill(&mut rng);
+        for _ in 0..CAVE_SMOOTHING_PASSES {
+            grid = smooth(&grid);
+        }
+        let region = keep_largest_region(&mut grid);
+        if (region.len() as f32) < (grid.width * grid.height) as f32 * MIN_CAVE_SHARE {
+            continue;
+        }
+        populate_cave(&mut grid, &region, depth, &mut rng);
+        return grid.to_lines();
+    }
+}
+
+fn random_fill(rng: &mut StdRng) -> Grid {
+    let mut grid = Grid::new(MAP_WIDTH, MAP_HEIGHT, '#');
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if !grid.is_border(x, y) && !rng.gen_bool(CAVE_FILL_CHANCE) {
+                grid.set(x, y, '.');
+            }
+        }
+    }
+    grid
+}
+
+// One step of the automaton: tiles crowded by walls turn to wall, open ones turn to
+// floor and tiles with exactly four walls around stay as they are
+fn smooth(grid: &Grid) -> Grid {
+    let mut next = Grid::new(grid.width, grid.height, '#');
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let walls = grid.walls_around(x, y);
+            let open = walls < 4 || (walls == 4 && grid.get(x, y) != '#');
+            if !grid.is_border(x, y) && open {
+                next.set(x, y, '.');
+            }
+        }
+    }
+    next
+}
+
+// Fills every open area but the largest with wall and returns the tiles of the one kept
+fn keep_largest_region(grid: &mut Grid) -> Vec<(usize, usize)> {
+    let mut seen = vec![false; grid.width * grid.height];
+    let mut largest: Vec<(usize, usize)> = Vec::new();
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if grid.get(x, y) == '#' || seen[y * grid.width + x] {
+                continue;
+            }
+            let distances = grid.distances_from((x, y));
+            let region: Vec<(usize, usize)> = (0..grid.width * grid.height)
+                .filter(|index| distances[*index].is_some())
+                .map(|index| (index % grid.width, index / grid.width))
+                .collect();
+            for (rx, ry) in region.iter() {
+                seen[ry * grid.width + rx] = true;
+            }
+            if region.len() > largest.len() {
+                largest = region;
+            }
+        }
+    }
+
+    let mut kept = vec![false; grid.width * grid.height];
+    for (x, y) in largest.iter() {
+        kept[y * grid.width + x] = true;
+    }
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if !kept[y * grid.width + x] {
+                grid.set(x, y, '#');
+            }
+        }
+    }
+    largest
+}
+
+// Puts the exit on the tile furthest from the player, which is reachable because the
+// cave is a single region, and enemies in open areas well away from the player
+fn populate_cave(grid: &mut Grid, region: &[(usize, usize)], depth: u32, rng: &mut StdRng) {
+    let start = *region.choose(rng).unwrap();
+    let distances = grid.distances_from(start);
+    let width = grid.width;
+    let distance = |(x, y): (usize, usize)| distances[y * width + x].unwrap_or(0);
+
+    let exit = *region.iter().max_by_key(|tile| distance(**tile)).unwrap();
+    grid.set(start.0, start.1, '@');
+    grid.set(exit.0, exit.1, '%');
+
+    // open areas have no wall on any of their 8 surrounding tiles
+    let mut enemy_spots: Vec<(usize, usize)> = region
+        .iter()
+        .copied()
+        .filter(|tile| grid.get(tile.0, tile.1) == '.')
+        .filter(|tile| grid.walls_around(tile.0, tile.1) == 0)
+        .filter(|tile| distance(*tile) >= MIN_ENEMY_DISTANCE)
+        .collect();
+    enemy_spots.shuffle(rng);
+    for (x, y) in enemy_spots.into_iter().take(2 + depth as usize) {
+        grid.set(x, y, 'g');
+    }
+
+    for _ in 0..rng.gen_range(1..=3) {
+        let (x, y) = *region.choose(rng).unwrap();
+        if grid.get(x, y) == '.' {
+            grid.set(x, y, *['$', '!', ')'].choose(rng).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUN_SEED: u64 = 0xD1CE;
+
+    fn count(lines: &[String], glyph: char) -> usize {
+        lines.iter().map(|line| line.chars().filter(|c| *c == glyph).count()).sum()
+    }
+
+    fn grid_from_lines(lines: &[String]) -> Grid {
+        Grid {
+            width: lines[0].chars().count(),
+            height: lines.len(),
+            cells: lines.iter().flat_map(|line| line.chars()).collect(),
+        }
+    }
+
+    // every tile with the glyph, in columns and rows
+    fn find(grid: &Grid, glyph: char) -> Vec<(usize, usize)> {
+        (0..grid.width * grid.height)
+            .map(|index| (index % grid.width, index / grid.width))
+            .filter(|(x, y)| grid.get(*x, *y) == glyph)
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_and_depth_build_the_same_level() {
+        for depth in 1..5 {
+            let seed = level_seed(RUN_SEED, depth);
+            assert_eq!(generate_level(seed, depth), generate_level(seed, depth));
+        }
+    }
+
+    #[test]
+    fn every_depth_gets_its_own_level() {
+        let levels: Vec<Vec<String>> = (1..5)
+            .map(|depth| generate_level(level_seed(RUN_SEED, depth), depth))
+            .collect();
+        for (i, level) in levels.iter().enumerate() {
+            for other in levels.iter().skip(i + 1) {
+                assert_ne!(level, other);
+            }
+        }
+    }
+
+    #[test]
+    fn every_level_has_one_start_and_an_exit() {
+        for seed in 0..100 {
+            let lines = generate_level(seed, 1 + seed as u32 % 5);
+            assert_eq!(lines.len(), MAP_HEIGHT);
+            assert!(lines.iter().all(|line| line.chars().count() == MAP_WIDTH));
+            assert_eq!(count(&lines, '@'), 1, "seed {seed}");
+            assert!(count(&lines, '%') >= 1, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn exit_can_be_reached_from_the_start() {
+        for seed in 0..200 {
+            let grid = grid_from_lines(&generate_level(seed, 1 + seed as u32 % 5));
+            let distances = grid.distances_from(find(&grid, '@')[0]);
+            for (x, y) in find(&grid, '%') {
+                assert!(distances[y * grid.width + x].is_some(), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn cave_enemies_start_away_from_the_player() {
+        for seed in 0..100 {
+            let grid = grid_from_lines(&generate_cave(seed, 5));
+            let distances = grid.distances_from(find(&grid, '@')[0]);
+            for (x, y) in find(&grid, 'g') {
+                let distance = distances[y * grid.width + x].unwrap();
+                assert!(distance >= MIN_ENEMY_DISTANCE, "seed {seed}: enemy {distance} steps away");
+            }
+        }
+    }
+}
@@ -1,26 +1,26 @@
-use bevy::{app::AppExit, prelude::*};
+use bevy::{app::AppExit, prelude::*, ui::UiSystem};
 
-use crate::{gamestate::*, map::GameLevel};
+use crate::{
+    gamestate::*,
+    level::LevelManifest,
+    map::{Entrance, RunSeed},
+    pause::PauseState,
+    save::{delete_save, load_save, save_exists, LoadedSave},
+    settings::{DisplayQuality, Volume},
+    actions::{update_action_state, Action, ActionState, KeyBindings, MenuInput, Preset},
+    summary::RunStats,
+};
 
-const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
-
-// One of the two settings that can be set through the menu. It will be a resource in the app
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
-enum DisplayQuality {
-    Low,
-    Medium,
-    High,
-}
-
-// One of the two settings that can be set through the menu. It will be a resource in the app
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
-struct Volume(u32);
+pub const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
 
 
-// This plugin manages the menu, with 5 different screens:
+// This plugin manages the menu, with 6 different screens:
 // - a main menu with "New Game", "Settings", "Quit"
-// - a settings menu with two submenus and a back button
+// - a settings menu with three submenus and a back button
 // - two settings screen with a setting that can be set and a back button
+// - a controls screen to rebind the key of every action
+// Besides the mouse, every screen can be driven with the arrow keys, Tab, Enter and
+// Backspace, or with a gamepad
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
@@ -30,6 +30,12 @@ impl Plugin for MenuPlugin {
             // entering the `GameState::Menu` state.
             // Current screen in the menu is handled by an independent state from `GameState`
             .add_state::<MenuState>()
+            .init_resource::<FocusPress>()
+            // buttons pressed through focus are pressed before any screen checks its buttons
+            .add_systems(
+                PreUpdate,
+                navigate_focus.after(UiSystem::Focus).after(update_action_state),
+            )
             .add_systems(OnEnter(GameState::Menu), menu_setup)
             // Systems to handle the main menu screen
             .add_systems(OnEnter(MenuState::Main), main_menu_setup)
@@ -66,21 +72,39 @@ impl Plugin for MenuPlugin {
                 OnExit(MenuState::SettingsSound),
                 despawn_screen::<OnSoundSettingsMenuScreen>,
             )
-            // Common systems to all screens that does buttons behavior
+            // Systems to handle the controls settings screen
+            .add_systems(
+                OnEnter(MenuState::SettingsControls),
+                controls_settings_menu_setup,
+            )
+            .add_systems(
+                Update,
+                (controls_button, capture_rebind_key, update_binding_labels)
+                    .chain()
+                    .run_if(in_state(MenuState::SettingsControls)),
+            )
+            .add_systems(
+                OnExit(MenuState::SettingsControls),
+                (despawn_screen::<OnControlsSettingsMenuScreen>, cancel_rebind),
+            )
+            // Common systems to all screens that does buttons behavior, the settings
+            // screens can also be opened from the pause menu
             .add_systems(
                 Update,
-                (menu_action, button_system).run_if(in_state(GameState::Menu)),
+                (menu_action, button_system)
+                    .run_if(in_state(GameState::Menu).or_else(in_state(PauseState::Settings))),
             );
     }
 }
 
 // State used for the current menu screen
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
-enum MenuState {
+pub enum MenuState {
     Main,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
     #[default]
     Disabled,
 }
@@ -101,40 +125,173 @@ struct OnDisplaySettingsMenuScreen;
 #[derive(Component)]
 struct OnSoundSettingsMenuScreen;
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+// Tag component used to tag entities added on the controls settings menu screen
+#[derive(Component)]
+struct OnControlsSettingsMenuScreen;
+
+// Button that rebinds an action to the next key pressed
+#[derive(Component)]
+struct RebindButton(Action);
+
+// Action waiting for its new key on the controls screen
+#[derive(Resource, Default)]
+struct Rebinding(Option<Action>);
+
+pub const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const HOVERED_PRESSED_BUTTON: Color = Color::rgb(0.25, 0.65, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+const FOCUSED_BUTTON: Color = Color::rgb(0.2, 0.3, 0.5);
+const FOCUSED_PRESSED_BUTTON: Color = Color::rgb(0.3, 0.6, 0.6);
 
 // Tag component used to mark which setting is currently selected
 #[derive(Component)]
-struct SelectedOption;
+pub struct SelectedOption;
+
+// Tag component used to mark the button picked with the keyboard or a gamepad,
+// confirming presses it
+#[derive(Component)]
+pub struct Focused;
+
+// Button pressed through focus, released again on the next frame
+#[derive(Resource, Default)]
+struct FocusPress(Option<Entity>);
+
+// Tag component used to tag the text telling why the save could not be continued
+#[derive(Component)]
+struct SaveErrorText;
 
 // All actions that can be triggered from a button click
 #[derive(Component)]
 enum MenuButtonAction {
+    Continue,
     Play,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
     BackToMainMenu,
     BackToSettings,
     Quit,
 }
 
+fn button_color(interaction: Interaction, selected: bool, focused: bool) -> BackgroundColor {
+    match (interaction, selected) {
+        (Interaction::Pressed, _) => PRESSED_BUTTON.into(),
+        (Interaction::Hovered, true) => HOVERED_PRESSED_BUTTON.into(),
+        (Interaction::Hovered, false) => HOVERED_BUTTON.into(),
+        // focus has colours of its own so it isn't lost under the mouse
+        (Interaction::None, true) if focused => FOCUSED_PRESSED_BUTTON.into(),
+        (Interaction::None, true) => PRESSED_BUTTON.into(),
+        (Interaction::None, false) if focused => FOCUSED_BUTTON.into(),
+        (Interaction::None, false) => NORMAL_BUTTON.into(),
+    }
+}
+
 // This system handles changing all buttons color based on mouse interaction
-fn button_system(
+pub fn button_system(
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
+        (&Interaction, &mut BackgroundColor, Has<SelectedOption>, Has<Focused>),
         (Changed<Interaction>, With<Button>),
     >,
 ) {
-    for (interaction, mut color, selected) in &mut interaction_query {
-        *color = match (*interaction, selected) {
-            (Interaction::Pressed, _) | (Interaction::None, Some(_)) => PRESSED_BUTTON.into(),
-            (Interaction::Hovered, Some(_)) => HOVERED_PRESSED_BUTTON.into(),
-            (Interaction::Hovered, None) => HOVERED_BUTTON.into(),
-            (Interaction::None, None) => NORMAL_BUTTON.into(),
+    for (interaction, mut color, selected, focused) in &mut interaction_query {
+        *color = button_color(*interaction, selected, focused);
+    }
+}
+
+// This system moves the focus through the buttons on screen in reading order and
+// presses the focused one on confirm. Back presses the back button of the screen.
+// Pressing sets the `Interaction` like a click would, so every screen handles it
+// with the systems it already has.
+fn navigate_focus(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    rebinding: Option<Res<Rebinding>>,
+    mut focus_press: ResMut<FocusPress>,
+    mut button_query: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &mut Interaction,
+            &mut BackgroundColor,
+            Has<SelectedOption>,
+            Has<Focused>,
+            Option<&MenuButtonAction>,
+        ),
+        With<Button>,
+    >,
+) {
+    // release the button pressed on the last frame so it can be pressed again
+    if let Some(entity) = focus_press.0.take() {
+        if let Ok((.., mut interaction, _, _, _, _)) = button_query.get_mut(entity) {
+            *interaction = Interaction::None;
+        }
+    }
+
+    // the keys pressed while rebinding are for the binding, not the menu
+    if rebinding.is_some_and(|rebinding| rebinding.0.is_some()) {
+        return;
+    }
+
+    // top to bottom, then left to right
+    let mut buttons: Vec<(Entity, Vec3, bool)> = button_query
+        .iter()
+        .map(|(entity, transform, .., focused, _)| (entity, transform.translation(), focused))
+        .collect();
+    if buttons.is_empty() {
+        return;
+    }
+    buttons.sort_by(|(_, a, _), (_, b, _)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    let focused = buttons.iter().position(|(.., focused)| *focused);
+
+    let step = if action_state.menu_just_pressed(MenuInput::Previous) {
+        -1
+    } else if action_state.menu_just_pressed(MenuInput::Next) {
+        1
+    } else {
+        0
+    };
+    let mut target = focused;
+    if step != 0 {
+        let next = match focused {
+            Some(index) => (index as i32 + step).rem_euclid(buttons.len() as i32) as usize,
+            None => 0,
+        };
+        if let Some(index) = focused {
+            let entity = buttons[index].0;
+            commands.entity(entity).remove::<Focused>();
+            if let Ok((_, _, interaction, mut color, selected, ..)) = button_query.get_mut(entity) {
+                *color = button_color(*interaction, selected, false);
+            }
+        }
+        let entity = buttons[next].0;
+        commands.entity(entity).insert(Focused);
+        if let Ok((_, _, interaction, mut color, selected, ..)) = button_query.get_mut(entity) {
+            *color = button_color(*interaction, selected, true);
+        }
+        target = Some(next);
+    }
+
+    let pressed = if action_state.menu_just_pressed(MenuInput::Confirm) {
+        target.map(|index| buttons[index].0)
+    } else if action_state.menu_just_pressed(MenuInput::Back) {
+        button_query
+            .iter()
+            .find(|(.., action)| {
+                matches!(
+                    action,
+                    Some(MenuButtonAction::BackToMainMenu | MenuButtonAction::BackToSettings)
+                )
+            })
+            .map(|(entity, ..)| entity)
+    } else {
+        None
+    };
+    if let Some(entity) = pressed {
+        if let Ok((_, _, mut interaction, ..)) = button_query.get_mut(entity) {
+            *interaction = Interaction::Pressed;
+            focus_press.0 = Some(entity);
         }
     }
 }
@@ -143,14 +300,14 @@ fn button_system(
 // the button as the one currently selected
 fn setting_button<T: Resource + Component + PartialEq + Copy>(
     interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
-    mut selected_query: Query<(Entity, &mut BackgroundColor), With<SelectedOption>>,
+    mut selected_query: Query<(Entity, &mut BackgroundColor, Has<Focused>), With<SelectedOption>>,
     mut commands: Commands,
     mut setting: ResMut<T>,
 ) {
     for (interaction, button_setting, entity) in &interaction_query {
         if *interaction == Interaction::Pressed && *setting != *button_setting {
-            let (previous_button, mut previous_color) = selected_query.single_mut();
-            *previous_color = NORMAL_BUTTON.into();
+            let (previous_button, mut previous_color, focused) = selected_query.single_mut();
+            *previous_color = button_color(Interaction::None, false, focused);
             commands.entity(previous_button).remove::<SelectedOption>();
             commands.entity(entity).insert(SelectedOption);
             *setting = *button_setting;
@@ -228,10 +385,34 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                         }),
                     );
 
-                    // Display three buttons for each action available from the main menu:
+                    // Display a button for each action available from the main menu:
+                    // - continue, when there is a saved run
                     // - new game
                     // - settings
                     // - quit
+                    if save_exists() {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                MenuButtonAction::Continue,
+                            ))
+                            .with_children(|parent| {
+                                let icon = asset_server.load("menu/right.png");
+                                parent.spawn(ImageBundle {
+                                    style: button_icon_style.clone(),
+                                    image: UiImage::new(icon),
+                                    ..default()
+                                });
+                                parent.spawn(TextBundle::from_section(
+                                    "Continue",
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
                     parent
                         .spawn((
                             ButtonBundle {
@@ -292,6 +473,23 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             });
                             parent.spawn(TextBundle::from_section("Quit", button_text_style));
                         });
+                    // Empty until continuing the saved run fails
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 25.0,
+                                color: TEXT_COLOR,
+                                ..default()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(20.0)),
+                            max_width: Val::Px(600.0),
+                            ..default()
+                        }),
+                        SaveErrorText,
+                    ));
                 });
         });
 }
@@ -341,6 +539,7 @@ fn settings_menu_setup(mut commands: Commands) {
                     for (action, text) in [
                         (MenuButtonAction::SettingsDisplay, "Display"),
                         (MenuButtonAction::SettingsSound, "Sound"),
+                        (MenuButtonAction::SettingsControls, "Controls"),
                         (MenuButtonAction::BackToMainMenu, "Back"),
                     ] {
                         parent
@@ -556,6 +755,212 @@ fn sound_settings_menu_setup(mut commands: Commands, volume: Res<Volume>) {
         });
 }
 
+fn controls_settings_menu_setup(mut commands: Commands, key_bindings: Res<KeyBindings>) {
+    let button_style = Style {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    // one button per action, smaller so they all fit on the screen
+    let binding_button_style = Style {
+        width: Val::Px(450.0),
+        height: Val::Px(40.0),
+        margin: UiRect::all(Val::Px(4.0)),
+        ..button_style.clone()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    let binding_text_style = TextStyle {
+        font_size: 30.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+
+    commands.insert_resource(Rebinding::default());
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnControlsSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    // Display a button for each action showing its key, click it and press
+                    // a key to rebind the action
+                    for action in Action::ALL {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: binding_button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                RebindButton(action),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    binding_label(action, &key_bindings, None),
+                                    binding_text_style.clone(),
+                                ));
+                            });
+                    }
+                    // Display a button for each preset, replacing all the bindings at once
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::CRIMSON.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Presets",
+                                binding_text_style.clone(),
+                            ));
+                            for preset in Preset::ALL {
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                width: Val::Px(150.0),
+                                                height: Val::Px(45.0),
+                                                margin: UiRect::all(Val::Px(10.0)),
+                                                ..button_style.clone()
+                                            },
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        preset,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            preset.label(),
+                                            binding_text_style.clone(),
+                                        ));
+                                    });
+                            }
+                        });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn binding_label(action: Action, key_bindings: &KeyBindings, rebinding: Option<Action>) -> String {
+    if rebinding == Some(action) {
+        return format!("{}: press a key", action.label());
+    }
+    match key_bindings.key(action) {
+        Some(key) => format!("{}: {key:?}", action.label()),
+        None => format!("{}: unbound", action.label()),
+    }
+}
+
+// This system starts rebinding the clicked action, or applies the clicked preset
+fn controls_button(
+    interaction_query: Query<
+        (&Interaction, Option<&RebindButton>, Option<&Preset>),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+) {
+    for (interaction, rebind_button, preset) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(RebindButton(action)) = rebind_button {
+            rebinding.0 = Some(*action);
+        } else if let Some(preset) = preset {
+            *key_bindings = KeyBindings::preset(*preset);
+            rebinding.0 = None;
+        }
+    }
+}
+
+fn capture_rebind_key(
+    keyboard_input: Res<Input<KeyCode>>,
+    action_state: Res<ActionState>,
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    // the key that started rebinding, like Enter, isn't the new binding
+    if rebinding.is_changed() {
+        return;
+    }
+    // back and pause give up on the rebind and keep the old key
+    if action_state.menu_just_pressed(MenuInput::Back) || action_state.just_pressed(Action::Pause) {
+        rebinding.0 = None;
+        return;
+    }
+    if let Some(key) = keyboard_input.get_just_pressed().next() {
+        key_bindings.bind(action, *key);
+        rebinding.0 = None;
+    }
+}
+
+// a rebind left pending would keep the menu from being navigated anywhere else
+fn cancel_rebind(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+fn update_binding_labels(
+    key_bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    button_query: Query<(&RebindButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !key_bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (RebindButton(action), children) in button_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = binding_label(*action, &key_bindings, rebinding.0);
+            }
+        }
+    }
+}
+
 fn menu_action(
     interaction_query: Query<
         (&Interaction, &MenuButtonAction),
@@ -564,6 +969,11 @@ fn menu_action(
     mut app_exit_events: EventWriter<AppExit>,
     mut menu_state: ResMut<NextState<MenuState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    current_pause_state: Res<State<PauseState>>,
+    manifest: Res<LevelManifest>,
+    mut commands: Commands,
+    mut save_error_query: Query<&mut Text, With<SaveErrorText>>,
 ) {
     for (interaction, menu_button_action) in &interaction_query {
         if *interaction == Interaction::Pressed {
@@ -571,17 +981,45 @@ fn menu_action(
                 MenuButtonAction::Quit => {
                     app_exit_events.send(AppExit);
                 }
+                MenuButtonAction::Continue => match load_save(&manifest) {
+                    Ok((level, save)) => {
+                        commands.insert_resource(save.seed());
+                        commands.insert_resource(Entrance::default());
+                        game_state.set(GameState::Game(level));
+                        commands.insert_resource(LoadedSave(save));
+                        menu_state.set(MenuState::Disabled);
+                    }
+                    Err(error) => {
+                        warn!("Could not continue the saved run: {error}");
+                        for mut text in save_error_query.iter_mut() {
+                            text.sections[0].value = format!("Could not continue: {error}");
+                        }
+                    }
+                },
                 MenuButtonAction::Play => {
-                    game_state.set(GameState::Game(GameLevel::Level1));
+                    // a new run replaces the saved one, it can't be continued afterwards
+                    delete_save();
+                    commands.insert_resource(RunSeed::from_env_or_random());
+                    commands.insert_resource(Entrance::default());
+                    commands.insert_resource(RunStats::default());
+                    game_state.set(GameState::Game(manifest.start));
                     menu_state.set(MenuState::Disabled);
                 }
                 MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
                 MenuButtonAction::SettingsDisplay => {
                     menu_state.set(MenuState::SettingsDisplay);
                 }
+                MenuButtonAction::SettingsControls => {
+                    menu_state.set(MenuState::SettingsControls);
+                }
                 MenuButtonAction::SettingsSound => {
                     menu_state.set(MenuState::SettingsSound);
                 }
+                // settings opened from the pause menu go back to it instead
+                MenuButtonAction::BackToMainMenu if *current_pause_state.get() == PauseState::Settings => {
+                    menu_state.set(MenuState::Disabled);
+                    pause_state.set(PauseState::Paused);
+                }
                 MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
                 MenuButtonAction::BackToSettings => {
                     menu_state.set(MenuState::Settings);
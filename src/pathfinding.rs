@@ -1,9 +1,13 @@
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{prelude::*, utils::HashMap};
 
 use crate::{player::{PLAYER_SIZE, 
     Player, wall_collision_check}, 
-    map::{TileCollider, GameLevel},
-    gamestate::GameState,
+    map::TileMap,
+    gamestate::in_game,
+    ai::{AiState, Guard, PatrolRoute},
+    stun::Stunned,
 };
 
 pub struct PathfinderPlugin;
@@ -13,74 +17,449 @@ impl Plugin for PathfinderPlugin {
         app
             .add_systems(
                 FixedUpdate, (
-                move_towards_player,
-            ).run_if(in_state(GameState::Game(GameLevel::Level1))));
+                update_flow_field,
+                move_pathfinders,
+            ).chain().run_if(in_game))
+            .init_resource::<FlowField>();
     }
 }
 
+// path costs are in tenths of a tile so diagonals can stay whole numbers
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+// gives up on paths that are not found after looking at this many cells
+const MAX_SEARCHED_CELLS: usize = 4000;
+const REPLAN_SECONDS: f32 = 0.5;
+// the shared flow field stops this far from the player, in path cost
+const FLOW_FIELD_RANGE: i32 = 30 * STRAIGHT_COST as i32;
+const FLEE_SCALE: f32 = -1.2;
+const UNREACHED: i32 = i32::MAX;
+
 #[derive(Component)]
 pub struct Pathinder {
+    // how far the pathfinder can see when nothing is in the way
     pub vision: f32,
     pub speed: f32,
+    // cell the player was last seen on, investigated after losing sight of them
+    pub last_seen: Option<IVec2>,
+}
+
+// Route a pathfinder is following with its own A* search, used to patrol, return to
+// its post, investigate where the player was last seen and when it spots the player
+// from outside the shared flow field. Re-planned when the goal moves to
+// another cell and every `REPLAN_SECONDS` in case the map changed.
+#[derive(Component)]
+pub struct PathPlan {
+    pub path: Vec<IVec2>,
+    pub goal: Option<IVec2>,
+    pub replan: Timer,
+}
+
+impl PathPlan {
+    // Next cell on the way to the goal, searching again only when the cached path is stale
+    pub fn next_step(&mut self, tile_map: &TileMap, cell: IVec2, goal: IVec2) -> Option<IVec2> {
+        if self.goal != Some(goal) || self.replan.just_finished() || self.path.is_empty() {
+            self.path = find_path(tile_map, cell, goal).unwrap_or_default();
+            self.goal = Some(goal);
+        }
+        // drop the cells already walked into
+        if let Some(reached) = self.path.iter().position(|step| *step == cell) {
+            self.path.drain(..=reached);
+        }
+        self.path.first().copied()
+    }
+}
+
+impl Default for PathPlan {
+    fn default() -> Self {
+        PathPlan {
+            path: Vec::new(),
+            goal: None,
+            replan: Timer::from_seconds(REPLAN_SECONDS, TimerMode::Repeating),
+        }
+    }
 }
 
+// the player is seen when they are within the vision radius and no wall is in the way
 pub fn find_player_location(
+    tile_map: &TileMap,
     target_player_position: Vec3,
     pathfinder_position: Vec3,
     pathfinder_vision: f32,
 ) -> bool { 
-        let collision = collide(
-            target_player_position,
-            Vec2::splat(PLAYER_SIZE),
-            pathfinder_position,
-            Vec2::splat(pathfinder_vision),
-        );
-
-        if collision.is_some() {
-            return true;
-        } 
-    false
+    let in_range = target_player_position.truncate().distance(pathfinder_position.truncate()) <= pathfinder_vision;
+    in_range && tile_map.line_of_sight(
+        TileMap::world_to_grid(pathfinder_position),
+        TileMap::world_to_grid(target_player_position),
+    )
 }
 
-pub fn move_towards_player(
-    wall_query: Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>,
+// Moves every pathfinder the way its `AiState` asks for
+pub fn move_pathfinders(
+    tile_map: Res<TileMap>,
+    flow_field: Res<FlowField>,
+    patrol_route: Res<PatrolRoute>,
     player_transform_query: Query<&Transform, With<Player>>,
-    mut pathfinder_query: Query<(&mut Transform, &Pathinder), Without<Player>>,
+    // stunned enemies stand still until it wears off
+    mut pathfinder_query: Query<(&mut Transform, &mut Pathinder, &mut PathPlan, &mut Guard, &AiState), (Without<Player>, Without<Stunned>)>,
     time: Res<Time>,
-    
 ) {
     let player_transform = player_transform_query.single();
-    for (mut pathfinder_transform, pathfinder) in pathfinder_query.iter_mut() {
-        if find_player_location(
-            player_transform.translation, 
-            pathfinder_transform.translation, 
-            pathfinder.vision
-        ) {
-            let mut x_delta = 0.0;
-            if pathfinder_transform.translation.x < player_transform.translation.x {
-                x_delta += pathfinder.speed * time.delta_seconds();
+    let player_cell = TileMap::world_to_grid(player_transform.translation);
+    for (mut pathfinder_transform, mut pathfinder, mut plan, mut guard, state) in pathfinder_query.iter_mut() {
+        plan.replan.tick(time.delta());
+        let cell = TileMap::world_to_grid(pathfinder_transform.translation);
+
+        let next_cell = match state {
+            AiState::Attack => None,
+            AiState::Chase => {
+                // close enough to attack, stay put instead of walking into the player
+                if pathfinder_transform.translation.truncate().distance(player_transform.translation.truncate()) < PLAYER_SIZE {
+                    continue;
+                }
+                if flow_field.reaches(cell) {
+                    flow_field.next_step(&tile_map, FlowMap::Chase, cell)
+                } else {
+                    plan.next_step(&tile_map, cell, player_cell)
+                }
+            }
+            AiState::Flee => flow_field.next_step(&tile_map, FlowMap::Flee, cell),
+            AiState::Investigate => {
+                let Some(last_seen) = pathfinder.last_seen else {
+                    continue;
+                };
+                let next_cell = plan.next_step(&tile_map, cell, last_seen);
+                if cell == last_seen || next_cell.is_none() {
+                    pathfinder.last_seen = None;
+                }
+                next_cell
+            }
+            AiState::Return => {
+                let next_cell = plan.next_step(&tile_map, cell, guard.post);
+                // the post can't be reached from here, guard this cell instead
+                if next_cell.is_none() {
+                    guard.stay_at(cell);
+                }
+                next_cell
+            }
+            AiState::Patrol => guard
+                .next_waypoint(&patrol_route, cell)
+                .and_then(|waypoint| plan.next_step(&tile_map, cell, waypoint)),
+        };
+        let Some(next_cell) = next_cell else {
+            continue;
+        };
+
+        // the last step of a chase heads for the player itself rather than the middle of their cell
+        let target = if next_cell == player_cell {
+            player_transform.translation
+        } else {
+            TileMap::grid_to_world(next_cell, pathfinder_transform.translation.z)
+        };
+        let step = pathfinder.speed * time.delta_seconds();
+        step_towards(&mut pathfinder_transform, target, step, &tile_map);
+    }
+}
+
+// moves up to `step` towards the target, one axis at a time so walls only stop the blocked axis
+fn step_towards(
+    transform: &mut Transform,
+    target: Vec3,
+    step: f32,
+    tile_map: &TileMap,
+) {
+    let x_delta = (target.x - transform.translation.x).clamp(-step, step);
+    let y_delta = (target.y - transform.translation.y).clamp(-step, step);
+
+    let target_position = transform.translation + Vec3::new(x_delta, 0.0, 0.0);
+    if wall_collision_check(target_position, tile_map) {
+        transform.translation = target_position;
+    }
+
+    let target_position = transform.translation + Vec3::new(0.0, y_delta, 0.0);
+    if wall_collision_check(target_position, tile_map) {
+        transform.translation = target_position;
+    }
+}
+
+// Distance from every cell to the player, or how good a cell is to run away to,
+// shared by every pathfinder. Only rebuilt when the player changes cell.
+#[derive(Resource, Default)]
+pub struct FlowField {
+    origin: Option<IVec2>,
+    width: i32,
+    chase: Vec<i32>,
+    flee: Vec<i32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlowMap {
+    // lower values are closer to the player
+    Chase,
+    // lower values are safer from the player
+    Flee,
+}
+
+impl FlowField {
+    fn value(&self, map: FlowMap, cell: IVec2) -> Option<i32> {
+        let values = match map {
+            FlowMap::Chase => &self.chase,
+            FlowMap::Flee => &self.flee,
+        };
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.width {
+            return None;
+        }
+        values
+            .get((cell.y * self.width + cell.x) as usize)
+            .copied()
+            .filter(|value| *value != UNREACHED)
+    }
+
+    // false for cells too far from the player for the field to cover
+    pub fn reaches(&self, cell: IVec2) -> bool {
+        self.value(FlowMap::Chase, cell).is_some()
+    }
+
+    // The neighbouring cell that goes furthest downhill, `None` when already at the bottom
+    pub fn next_step(&self, tile_map: &TileMap, map: FlowMap, cell: IVec2) -> Option<IVec2> {
+        let mut best = (cell, self.value(map, cell)?);
+        for (neighbour, _) in walkable_neighbours(tile_map, cell) {
+            if let Some(value) = self.value(map, neighbour) {
+                if value < best.1 {
+                    best = (neighbour, value);
+                }
             }
-            if pathfinder_transform.translation.x > player_transform.translation.x {
-                x_delta -= pathfinder.speed * time.delta_seconds();
+        }
+        (best.0 != cell).then_some(best.0)
+    }
+}
+
+fn update_flow_field(
+    tile_map: Res<TileMap>,
+    player_transform_query: Query<&Transform, With<Player>>,
+    mut flow_field: ResMut<FlowField>,
+) {
+    let player_cell = TileMap::world_to_grid(player_transform_query.single().translation);
+    if flow_field.origin == Some(player_cell) && !tile_map.is_changed() {
+        return;
+    }
+
+    let chase = dijkstra_map(&tile_map, &[(player_cell, 0)], FLOW_FIELD_RANGE);
+    // scaling the chase map past -1 and letting it settle again makes fleeing enemies
+    // head for open space further away instead of cornering themselves
+    let flee_seeds: Vec<(IVec2, i32)> = chase
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value != UNREACHED)
+        .map(|(index, value)| {
+            let cell = IVec2::new(index as i32 % tile_map.width, index as i32 / tile_map.width);
+            (cell, (*value as f32 * FLEE_SCALE) as i32)
+        })
+        .collect();
+    let flee = dijkstra_map(&tile_map, &flee_seeds, i32::MAX);
+
+    *flow_field = FlowField {
+        origin: Some(player_cell),
+        width: tile_map.width,
+        chase,
+        flee,
+    };
+}
+
+// Cost of the cheapest walk from any of the seeds to every cell, starting from the
+// seed's own value. Cells further than `max_cost` are left `UNREACHED`.
+fn dijkstra_map(tile_map: &TileMap, seeds: &[(IVec2, i32)], max_cost: i32) -> Vec<i32> {
+    let mut costs = vec![UNREACHED; (tile_map.width * tile_map.height) as usize];
+    let index = |cell: IVec2| (cell.y * tile_map.width + cell.x) as usize;
+    let mut open = BinaryHeap::new();
+    for &(cell, cost) in seeds.iter() {
+        if tile_map.is_walkable(cell) && cost < costs[index(cell)] {
+            costs[index(cell)] = cost;
+            open.push(Reverse((cost, cell.x, cell.y)));
+        }
+    }
+
+    while let Some(Reverse((cost, x, y))) = open.pop() {
+        let cell = IVec2::new(x, y);
+        if cost > costs[index(cell)] {
+            continue;
+        }
+        for (neighbour, step_cost) in walkable_neighbours(tile_map, cell) {
+            let next_cost = cost.saturating_add(step_cost as i32);
+            if next_cost <= max_cost && next_cost < costs[index(neighbour)] {
+                costs[index(neighbour)] = next_cost;
+                open.push(Reverse((next_cost, neighbour.x, neighbour.y)));
             }
+        }
+    }
+    costs
+}
+
+// Shortest walkable route between two cells with A*, moving in 8 directions. The
+// returned path starts with the first cell after `start` and ends on `goal`.
+pub fn find_path(
+    tile_map: &TileMap,
+    start: IVec2,
+    goal: IVec2,
+) -> Option<Vec<IVec2>> {
+    if !tile_map.is_walkable(goal) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+    let mut cost_so_far: HashMap<IVec2, u32> = HashMap::new();
+    open.push(Reverse((octile_distance(start, goal), start.x, start.y)));
+    cost_so_far.insert(start, 0);
 
-            let mut y_delta = 0.0;
-            if pathfinder_transform.translation.y < player_transform.translation.y {
-                y_delta += pathfinder.speed * time.delta_seconds();
+    let mut searched = 0;
+    while let Some(Reverse((priority, x, y))) = open.pop() {
+        let cell = IVec2::new(x, y);
+        // a cheaper way to this cell was found after this entry was queued
+        if priority > cost_so_far[&cell] + octile_distance(cell, goal) {
+            continue;
+        }
+        if cell == goal {
+            let mut path = vec![goal];
+            let mut current = goal;
+            while let Some(&previous) = came_from.get(&current) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                current = previous;
             }
-            if pathfinder_transform.translation.y > player_transform.translation.y {
-                y_delta -= pathfinder.speed * time.delta_seconds();
+            path.reverse();
+            return Some(path);
+        }
+
+        searched += 1;
+        if searched > MAX_SEARCHED_CELLS {
+            return None;
+        }
+
+        for (neighbour, step_cost) in walkable_neighbours(tile_map, cell) {
+            let cost = cost_so_far[&cell] + step_cost;
+            if cost_so_far.get(&neighbour).is_none_or(|&known| cost < known) {
+                cost_so_far.insert(neighbour, cost);
+                came_from.insert(neighbour, cell);
+                let priority = cost + octile_distance(neighbour, goal);
+                open.push(Reverse((priority, neighbour.x, neighbour.y)));
             }
+        }
+    }
+    None
+}
 
-            let target_pathfinder_position = pathfinder_transform.translation + Vec3::new(x_delta, 0.0, 0.0);
-            if wall_collision_check(target_pathfinder_position, &wall_query) {
-                pathfinder_transform.translation = target_pathfinder_position;
+// Cells that can be stepped to from `cell` with the cost of the step. Diagonal
+// steps are only allowed when they don't cut the corner of a wall.
+pub fn walkable_neighbours(tile_map: &TileMap, cell: IVec2) -> Vec<(IVec2, u32)> {
+    let mut neighbours = Vec::with_capacity(8);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let neighbour = cell + IVec2::new(dx, dy);
+            if !tile_map.is_walkable(neighbour) {
+                continue;
             }
+            if dx != 0 && dy != 0 {
+                let beside = tile_map.is_walkable(cell + IVec2::new(dx, 0))
+                    && tile_map.is_walkable(cell + IVec2::new(0, dy));
+                if beside {
+                    neighbours.push((neighbour, DIAGONAL_COST));
+                }
+            } else {
+                neighbours.push((neighbour, STRAIGHT_COST));
+            }
+        }
+    }
+    neighbours
+}
+
+// Admissible A* heuristic for 8 directional movement
+fn octile_distance(from: IVec2, to: IVec2) -> u32 {
+    let dx = (from.x - to.x).unsigned_abs();
+    let dy = (from.y - to.y).unsigned_abs();
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
 
-            let target_pathfinder_position = pathfinder_transform.translation + Vec3::new(0.0, y_delta, 0.0);
-            if wall_collision_check(target_pathfinder_position, &wall_query) {
-                pathfinder_transform.translation = target_pathfinder_position;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileKind;
+
+    // `#` is wall, anything else floor, rows top to bottom
+    fn tile_map(rows: &[&str]) -> TileMap {
+        let mut tile_map = TileMap::new(rows[0].len() as i32, rows.len() as i32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                if glyph != '#' {
+                    tile_map.set(IVec2::new(x as i32, y as i32), TileKind::Floor);
+                }
             }
         }
+        tile_map
+    }
+
+    fn path_cost(start: IVec2, path: &[IVec2]) -> u32 {
+        let mut cost = 0;
+        let mut cell = start;
+        for step in path {
+            let offset = (*step - cell).abs();
+            assert!(offset.max_element() == 1, "{cell} to {step} is not one step");
+            cost += if offset.x + offset.y == 2 { DIAGONAL_COST } else { STRAIGHT_COST };
+            cell = *step;
+        }
+        cost
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let tile_map = tile_map(&[
+            ".......",
+            "...#...",
+            "...#...",
+            "...#...",
+            ".......",
+        ]);
+        let (start, goal) = (IVec2::new(1, 2), IVec2::new(5, 2));
+        let path = find_path(&tile_map, start, goal).unwrap();
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.iter().all(|cell| tile_map.is_walkable(*cell)));
+
+        // as cheap as the Dijkstra map says the goal can be reached
+        let costs = dijkstra_map(&tile_map, &[(start, 0)], i32::MAX);
+        let goal_cost = costs[(goal.y * tile_map.width + goal.x) as usize];
+        assert_eq!(path_cost(start, &path) as i32, goal_cost);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn no_path_to_an_enclosed_goal() {
+        let tile_map = tile_map(&[
+            ".......",
+            "...###.",
+            "...#.#.",
+            "...###.",
+            ".......",
+        ]);
+        assert_eq!(find_path(&tile_map, IVec2::new(0, 0), IVec2::new(4, 2)), None);
+        // walls can't be a goal either
+        assert_eq!(find_path(&tile_map, IVec2::new(0, 0), IVec2::new(3, 2)), None);
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_corners() {
+        let tile_map = tile_map(&[
+            "...",
+            "#..",
+            "...",
+        ]);
+        let path = find_path(&tile_map, IVec2::new(0, 0), IVec2::new(1, 1)).unwrap();
+        assert_eq!(path, vec![IVec2::new(1, 0), IVec2::new(1, 1)]);
+
+        let path = find_path(&tile_map, IVec2::new(0, 2), IVec2::new(1, 1)).unwrap();
+        assert_eq!(path, vec![IVec2::new(1, 2), IVec2::new(1, 1)]);
+    }
+}
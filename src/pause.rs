@@ -0,0 +1,197 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    actions::{Action, ActionState},
+    gamestate::*,
+    menu::{button_system, MenuState, NORMAL_BUTTON, TEXT_COLOR},
+    save::{delete_save, SaveAndExit},
+};
+
+// This plugin pauses a run with the pause key, Escape by default. While paused the virtual clock stands still,
+// so nothing in `FixedUpdate` runs until the player resumes.
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_state::<PauseState>()
+            .add_systems(Update, toggle_pause.run_if(in_game))
+            .add_systems(OnExit(PauseState::Running), pause_time)
+            .add_systems(OnEnter(PauseState::Running), resume_time)
+            .add_systems(OnEnter(PauseState::Paused), pause_menu_setup)
+            .add_systems(OnExit(PauseState::Paused), despawn_screen::<OnPauseMenuScreen>)
+            .add_systems(
+                Update,
+                (pause_menu_action, button_system).run_if(in_state(PauseState::Paused)),
+            );
+    }
+}
+
+// State of the run on top of `GameState::Game`
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+pub enum PauseState {
+    #[default]
+    Running,
+    // the pause menu is shown
+    Paused,
+    // the settings screens of the main menu are shown over the paused run
+    Settings,
+}
+
+// Tag component used to tag entities added on the pause menu screen
+#[derive(Component)]
+struct OnPauseMenuScreen;
+
+// All actions that can be triggered from a button click
+#[derive(Component)]
+enum PauseButtonAction {
+    Resume,
+    Settings,
+    SaveAndExit,
+    AbandonRun,
+    Quit,
+}
+
+fn toggle_pause(
+    action_state: Res<ActionState>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    if !action_state.just_pressed(Action::Pause) {
+        return;
+    }
+    match pause_state.get() {
+        PauseState::Running => next_pause_state.set(PauseState::Paused),
+        PauseState::Paused => next_pause_state.set(PauseState::Running),
+        PauseState::Settings => {
+            menu_state.set(MenuState::Disabled);
+            next_pause_state.set(PauseState::Paused);
+        }
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn pause_menu_setup(mut commands: Commands) {
+    let button_style = Style {
+        width: Val::Px(300.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                // darken the level behind the menu
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            OnPauseMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            "Paused",
+                            TextStyle {
+                                font_size: 80.0,
+                                color: TEXT_COLOR,
+                                ..default()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(50.0)),
+                            ..default()
+                        }),
+                    );
+
+                    for (action, text) in [
+                        (PauseButtonAction::Resume, "Resume"),
+                        (PauseButtonAction::Settings, "Settings"),
+                        (PauseButtonAction::SaveAndExit, "Save & Exit"),
+                        (PauseButtonAction::AbandonRun, "Abandon Run"),
+                        (PauseButtonAction::Quit, "Quit to Desktop"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(text, button_text_style.clone()));
+                            });
+                    }
+                });
+        });
+}
+
+fn pause_menu_action(
+    interaction_query: Query<
+        (&Interaction, &PauseButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut save_events: EventWriter<SaveAndExit>,
+) {
+    for (interaction, pause_button_action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            match pause_button_action {
+                PauseButtonAction::Resume => pause_state.set(PauseState::Running),
+                PauseButtonAction::Settings => {
+                    pause_state.set(PauseState::Settings);
+                    menu_state.set(MenuState::Settings);
+                }
+                // leaves the run once it is written, see `write_save`
+                PauseButtonAction::SaveAndExit => save_events.send(SaveAndExit),
+                // leaving the game tears the level down, see `MapPlugin`
+                PauseButtonAction::AbandonRun => {
+                    delete_save();
+                    pause_state.set(PauseState::Running);
+                    game_state.set(GameState::Menu);
+                }
+                PauseButtonAction::Quit => {
+                    app_exit_events.send(AppExit);
+                }
+            }
+        }
+    }
+}
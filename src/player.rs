@@ -1,14 +1,17 @@
 use bevy::transform::components::Transform;
 
 use crate::enemy::{ENEMY_SIZE, Enemy};
-use crate::health::Health;
-use crate::gamestate::GameState;    
+use crate::health::{DamageEvent, DamageKind, Health};
+use crate::item::Inventory;
+use crate::projectile::{line_glyph, Ammo, STARTING_AMMO};
+use crate::sound::SoundEffect;
+use crate::stun::Stunned;
+use crate::actions::{Action, ActionState, ReadsFixedPresses};
+use crate::gamestate::in_game;
 
 use crate::ascii::*;
-use crate::map::{TileCollider, GameLevel};
-use crate::pathfinding::Pathinder;
+use crate::map::TileMap;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::{collide, Collision};
 
 pub struct PlayerPlugin;
 
@@ -19,25 +22,84 @@ impl Plugin for PlayerPlugin {
             FixedUpdate, (
                 player_movement, 
                 camera_follow, 
-                attack_enemy, 
-            ).run_if(in_state(GameState::Game(GameLevel::Level1))))
-            .add_systems(
-            FixedUpdate, (
-                player_movement, 
-                camera_follow, 
-                attack_enemy, 
-            ).run_if(in_state(GameState::Game(GameLevel::Level2))));
+                attack_enemy.in_set(ReadsFixedPresses),
+                animate_swing,
+            ).run_if(in_game));
     }
     
 }
 
 pub const PLAYER_SIZE: f32 = 50.0;
+pub const PLAYER_MAX_HEALTH: i32 = 100;
 const PLAYER_SPEED: f32 = 100.0;
+const KNOCK_BACK_DISTANCE: f32 = 100.0;
+const KNOCK_BACK_STEP: f32 = 5.0;
+// how far in front of the player an attack reaches, measured to the enemy's edge
+const ATTACK_REACH: f32 = PLAYER_SIZE * 1.5;
+// half the angle of the attack arc, so 120 degrees in total
+const ATTACK_HALF_ARC: f32 = std::f32::consts::PI / 3.0;
+const SWING_SECONDS: f32 = 0.15;
 
 #[derive(Component)]
 pub struct Player {
     speed: f32,
-    direction: u16,
+    timer: Timer,
+}
+
+// The way the player last moved in, attacks go this way. Holding two movement keys
+// faces diagonally.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Facing {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Facing {
+    // Nearest of the eight directions, none when not moving
+    pub fn from_movement(movement: Vec2) -> Option<Facing> {
+        if movement == Vec2::ZERO {
+            return None;
+        }
+        // counter clockwise in eighths of a turn, starting to the right
+        let eighth = (movement.y.atan2(movement.x) / std::f32::consts::FRAC_PI_4).round() as i32;
+        Some(match eighth.rem_euclid(8) {
+            0 => Facing::Right,
+            1 => Facing::UpRight,
+            2 => Facing::Up,
+            3 => Facing::UpLeft,
+            4 => Facing::Left,
+            5 => Facing::DownLeft,
+            6 => Facing::Down,
+            _ => Facing::DownRight,
+        })
+    }
+
+    // unit vector pointing this way, y up
+    pub fn vector(self) -> Vec2 {
+        let (x, y) = match self {
+            Facing::Up => (0.0, 1.0),
+            Facing::UpRight => (1.0, 1.0),
+            Facing::Right => (1.0, 0.0),
+            Facing::DownRight => (1.0, -1.0),
+            Facing::Down => (0.0, -1.0),
+            Facing::DownLeft => (-1.0, -1.0),
+            Facing::Left => (-1.0, 0.0),
+            Facing::UpLeft => (-1.0, 1.0),
+        };
+        Vec2::new(x, y).normalize()
+    }
+}
+
+// The blade of an attack sweeping through the arc in front of the player
+#[derive(Component)]
+pub struct Swing {
+    facing: Facing,
     timer: Timer,
 }
 
@@ -53,62 +115,42 @@ pub fn camera_follow(
 }
 
 pub fn player_movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    wall_query: Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>,
-    mut player_query: Query<(&mut Transform, &mut Player)>,
+    action_state: Res<ActionState>,
+    tile_map: Res<TileMap>,
+    mut player_query: Query<(&mut Transform, &Player, &mut Facing, Has<Stunned>)>,
     time: Res<Time>,
 ) {
-    let (mut transform, mut player) = player_query.single_mut(); 
-    
-    let mut y_delta = 0.0;
-    if keyboard_input.pressed(KeyCode::W) {
-        y_delta +=  player.speed * time.delta_seconds();
-        player.direction = 360;
-    }
-    if keyboard_input.pressed(KeyCode::S) {
-        y_delta -= player.speed * time.delta_seconds();
-        player.direction = 180;
-    }
-
-    let mut x_delta = 0.0;
-    if keyboard_input.pressed(KeyCode::A) {
-        x_delta -= player.speed * time.delta_seconds();
-        player.direction = 270;
+    let (mut transform, player, mut facing, stunned) = player_query.single_mut(); 
+    if stunned {
+        return;
     }
-    if keyboard_input.pressed(KeyCode::D) {
-        x_delta += player.speed * time.delta_seconds();
-        player.direction = 90;
+    
+    // keys and the D-pad move at full speed, a stick as far as it is pushed
+    let movement = action_state.movement();
+    let y_delta = movement.y * player.speed * time.delta_seconds();
+    let x_delta = movement.x * player.speed * time.delta_seconds();
+    // cause player movement to change player direction
+    if let Some(new_facing) = Facing::from_movement(movement) {
+        *facing = new_facing;
     }
 
     let target_player_position = transform.translation + Vec3::new(x_delta, 0.0, 0.0);
-    if wall_collision_check(target_player_position, &wall_query) {
+    if wall_collision_check(target_player_position, &tile_map) {
         transform.translation = target_player_position;
     }
 
     let target_player_position = transform.translation + Vec3::new(0.0, y_delta, 0.0);
-    if wall_collision_check(target_player_position, &wall_query) {
+    if wall_collision_check(target_player_position, &tile_map) {
         transform.translation = target_player_position;
     }
-    // cause player movement to change player direction
-
 }
 
+// true when something of the player's size fits at the target position
 pub fn wall_collision_check(
     target_player_position: Vec3,
-    wall_query: &Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>
+    tile_map: &TileMap,
 ) -> bool {
-    for wall_transform in wall_query.iter() {
-        let collision = collide(
-            target_player_position, 
-            Vec2::splat(PLAYER_SIZE * 0.9),
-            wall_transform.translation, 
-            Vec2::splat(PLAYER_SIZE)
-        );
-        if collision.is_some() {
-            return false;
-        }
-    }
-    true
+    !tile_map.is_blocked(target_player_position, PLAYER_SIZE * 0.9)
 }
 
 pub fn spawn_player(
@@ -132,13 +174,15 @@ pub fn spawn_player(
         })
         .insert(Player {
             speed: PLAYER_SPEED,
-            direction: 270,
             timer: Timer::from_seconds(3.0, TimerMode::Once)
         })
+        .insert(Facing::Left)
         .insert(Name::new("Player"))
         .insert(Health {
-            health: 100,
+            health: PLAYER_MAX_HEALTH,
         })
+        .insert(Inventory::default())
+        .insert(Ammo(STARTING_AMMO))
         .with_children(|parent| {
             parent
             .spawn(SpriteSheetBundle {
@@ -153,198 +197,127 @@ pub fn spawn_player(
 
 
 pub fn attack_enemy(
-    mut player_transform: Query<(&Transform, &mut Player)>,
-    mut enemy_query: Query<(&mut Transform, &mut Health), (With<Enemy>, Without<Player>, Without<TileCollider>)>,
-    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    mut player_transform: Query<(Entity, &Transform, &mut Player, &Facing, Has<Stunned>)>,
+    mut enemy_query: Query<(Entity, &mut Transform), (With<Enemy>, Without<Player>)>,
+    action_state: Res<ActionState>,
     time: Res<Time>,
-    wall_query: Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>,
+    tile_map: Res<TileMap>,
+    mut sound_effects: EventWriter<SoundEffect>,
+    mut damage_events: EventWriter<DamageEvent>,
 ) {
-    let (player_transform, mut player) = player_transform.single_mut();
+    let (player_entity, player_transform, mut player, facing, stunned) = player_transform.single_mut();
     player.timer.tick(time.delta());
+    // add attack timer to create cooldown effect for attacks
+    if stunned || !action_state.fixed_just_pressed(Action::Attack) || !player.timer.finished() {
+        return;
+    }
+    sound_effects.send(SoundEffect::PlayerAttack);
+    spawn_swing(&mut commands, &ascii, player_entity, *facing);
 
-    for (enemy_transform, mut enemy_health) in enemy_query.iter_mut() {
-            let collision = collide(
-                player_transform.translation,
-                Vec2::splat(PLAYER_SIZE * 3.0),
-                enemy_transform.translation,
-                Vec2::splat(ENEMY_SIZE),
-            );
+    let mut hit = false;
+    for (enemy_entity, enemy_transform) in enemy_query.iter_mut() {
+        if !in_attack_arc(player_transform.translation, *facing, enemy_transform.translation) {
+            continue;
+        }
+        damage_events.send(DamageEvent {
+            source: Some(player_entity),
+            target: enemy_entity,
+            amount: 1,
+            kind: DamageKind::Melee,
+        });
+        // add knock back effect based on player direction
+        knock_back(facing.vector(), &tile_map, enemy_transform);
+        hit = true;
+    }
+    if hit {
+        player.timer.reset();
+    }
+}
 
-            // add attack timer to create cooldown effect for attacks
-            if collision.is_some() && 
-            keyboard_input.just_pressed(KeyCode::Space) && 
-            player.timer.finished()
-            {
-                enemy_health.health -= 1;
-                println!("Enemy Health: {}", enemy_health.health);
-                player.timer.reset();
-                // add knock back effect based on player direction
-                knock_back(player_transform, &wall_query, enemy_transform);
-                
-            }
+// true when the enemy is close enough and in front of the player
+fn in_attack_arc(
+    player_translation: Vec3,
+    facing: Facing,
+    enemy_translation: Vec3,
+) -> bool {
+    let offset = (enemy_translation - player_translation).truncate();
+    if offset.length() - ENEMY_SIZE * 0.5 > ATTACK_REACH {
+        return false;
+    }
+    // standing on top of each other counts as in front
+    offset.length() < PLAYER_SIZE * 0.5 || facing.vector().angle_between(offset).abs() <= ATTACK_HALF_ARC
+}
+
+fn spawn_swing(
+    commands: &mut Commands,
+    ascii: &Res<AsciiSheet>,
+    player: Entity,
+    facing: Facing,
+) {
+    let mut sprite = TextureAtlasSprite::new('|' as usize);
+    sprite.color = Color::rgb(0.9, 0.9, 0.9);
+    sprite.custom_size = Some(Vec2::splat(PLAYER_SIZE));
+    let swing = commands.spawn(SpriteSheetBundle {
+        sprite,
+        texture_atlas: ascii.0.clone(),
+        ..default()
+    })
+    .insert(Swing {
+        facing,
+        timer: Timer::from_seconds(SWING_SECONDS, TimerMode::Once),
+    })
+    .insert(Name::new("Swing"))
+    .id();
+    commands.entity(player).add_child(swing);
+}
+
+// sweeps the blade from one side of the arc to the other, the glyph follows its angle
+fn animate_swing(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut swing_query: Query<(Entity, &mut Swing, &mut Transform, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut swing, mut transform, mut sprite) in swing_query.iter_mut() {
+        swing.timer.tick(time.delta());
+        if swing.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
         }
+        let angle = swing.facing.vector().y.atan2(swing.facing.vector().x)
+            + ATTACK_HALF_ARC * (1.0 - 2.0 * swing.timer.percent());
+        let direction = Vec2::from_angle(angle);
+        transform.translation = (direction * PLAYER_SIZE).extend(1.0);
+        sprite.index = line_glyph(direction);
+    }
 }
 
-// complete rework of knock back function, making knock based on 
-// enemy velocity instead of player direction
+// pushes whoever was hit away from the attacker, stopping early when it would end up in
+// a wall. Players and enemies are the same size, so it works the same for both.
 pub fn knock_back(
-    player_transform: &Transform,
-    wall_query: &Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>,
-    mut enemy_transform: Mut<'_, Transform>,
+    direction: Vec2,
+    tile_map: &TileMap,
+    mut transform: Mut<'_, Transform>,
 ) {
-        enemy_transform.translation = 
-        knock_back_measurement(
-            enemy_transform.translation, 
-            wall_query, 
-            knock_back_direction(player_transform.translation, enemy_transform.translation)
-        ) + 
-        enemy_transform.translation;
+    transform.translation = knock_back_target(transform.translation, tile_map, direction.extend(0.0));
 }
 
-pub fn knock_back_measurement(
-    enemy_position: Vec3,
-    wall_query: &Query<&Transform, (With<TileCollider>, Without<Player>, Without<Pathinder>)>, 
-    knock_back_direction: Collision,
+// slides from the start position in small steps so the first wall in the way stops it
+pub fn knock_back_target(
+    start: Vec3,
+    tile_map: &TileMap,
+    direction: Vec3,
 ) -> Vec3 {
-    let mut player_direction = 90;
-    let mut knock_back_prediction = Vec3::new(0.0, 0.0, 0.0);
-    match knock_back_direction {
-        Collision::Left => { // actually right
-            player_direction = 90;
-            knock_back_prediction = Vec3::new(100.0, 0.0, 0.0);
-            println!("Right")
-        }
-        Collision::Right => { // actually left
-            player_direction = 270;
-            knock_back_prediction = Vec3::new(-100.0, 0.0, 0.0);
-            println!("left")
-        }
-        Collision::Top => { // actually bottom
-            player_direction = 180;
-            knock_back_prediction = Vec3::new(0.0, -100.0, 0.0);
+    let mut position = start;
+    let mut travelled = 0.0;
+    while travelled < KNOCK_BACK_DISTANCE {
+        let next = position + direction * KNOCK_BACK_STEP;
+        if tile_map.is_blocked(next, ENEMY_SIZE * 0.9) {
+            break;
         }
-        Collision::Bottom => { // actually top
-            player_direction = 360;
-            knock_back_prediction = Vec3::new(0.0, 100.0, 0.0);
-        }
-        _ => {}
-    }
-    let mut perpendicular_walls: Vec<Vec3> = Vec::new();
-    for wall in wall_query.iter() {
-        match player_direction {
-            360 | 180 => {
-                if enemy_position.x + 45.0 > wall.translation.x && enemy_position.x - 45.0 < wall.translation.x {
-                    perpendicular_walls.push(wall.translation);
-                }
-            }
-            90 | 270 => {
-                if enemy_position.y + 45.0 > wall.translation.y && enemy_position.y - 45.0 < wall.translation.y {
-                    perpendicular_walls.push(wall.translation);
-                }
-            }
-            _ => {}
-        }
-    }
-    let mut collision_wall = Vec3::new(0.0, 0.0, 0.0);
-    match player_direction {
-        360 => {
-            let mut walls_above_enemy: Vec<&Vec3> = Vec::new();
-            for wall in perpendicular_walls.iter() {
-                if wall.y > enemy_position.y {
-                    walls_above_enemy.push(wall);
-                }
-            }
-            walls_above_enemy.sort_by(|a, b| {
-                a.x.partial_cmp(&b.x).unwrap()
-            });
-            collision_wall = **walls_above_enemy.last().unwrap();
-        }
-        270 => {
-            let mut walls_left_of_enemy: Vec<&Vec3> = Vec::new();
-            for wall in perpendicular_walls.iter() {
-                if wall.x < enemy_position.x {
-                    walls_left_of_enemy.push(wall);
-                }
-            }
-            walls_left_of_enemy.sort_by(|a, b| {
-                a.y.partial_cmp(&b.y).unwrap()
-            });
-            collision_wall = **walls_left_of_enemy.last().unwrap();
-        }
-        180 => {
-            let mut walls_below_enemy: Vec<&Vec3> = Vec::new();
-            for wall in perpendicular_walls.iter() {
-                if wall.y < enemy_position.y {
-                    walls_below_enemy.push(wall);
-                }
-            }
-            walls_below_enemy.sort_by(|a, b| {
-                a.x.partial_cmp(&b.x).unwrap()
-            });
-            collision_wall = walls_below_enemy[0].clone();
-        }
-        90 => {
-            let mut walls_right_of_enemy: Vec<&Vec3> = Vec::new();
-            for wall in perpendicular_walls.iter() {
-                if wall.x > enemy_position.x {
-                    walls_right_of_enemy.push(wall);
-                }
-            }
-            walls_right_of_enemy.sort_by(|a, b| {
-                a.y.partial_cmp(&b.y).unwrap()
-            });
-            dbg!(&walls_right_of_enemy);
-            collision_wall = walls_right_of_enemy[0].clone();
-        }
-        _ => ()
-    };
-    match player_direction {
-        360 => {
-            if knock_back_prediction.y + enemy_position.y + 50.0 > collision_wall.y {
-                return Vec3::new(0.0, collision_wall.y - enemy_position.y - 50.0, 0.0);
-            } else {
-                return knock_back_prediction;
-            }
-        }
-        90 => {
-            if knock_back_prediction.x + enemy_position.x + 50.0 > collision_wall.x {
-                return Vec3::new(collision_wall.x - enemy_position.x - 50.0, 0.0, 0.0);
-            }else {
-                dbg!(knock_back_prediction);
-                return knock_back_prediction;
-            }
-        }
-        180 => {
-            if knock_back_prediction.y + enemy_position.y - 50.0 < collision_wall.y {
-                return Vec3::new(0.0, collision_wall.y - enemy_position.y + 50.0, 0.0);
-            }else {
-                return knock_back_prediction;
-            }
-        }
-        270 => {
-            if knock_back_prediction.x + enemy_position.x - 50.0 < collision_wall.x {
-                return Vec3::new(collision_wall.x - enemy_position.x + 50.0, 0.0, 0.0);
-            } else {
-                return knock_back_prediction;
-            }
-        }
-        _ => Vec3::new(0.0, 0.0, 0.0)
+        position = next;
+        travelled += KNOCK_BACK_STEP;
     }
+    position
 }
-
-fn knock_back_direction(
-    player_translation: Vec3,
-    enemy_translation: Vec3,
-) -> Collision {
-    let collision = collide(
-        player_translation,
-        Vec2::splat(PLAYER_SIZE * 3.0),
-        enemy_translation,
-        Vec2::splat(ENEMY_SIZE),
-    );
-    if collision.is_some() {
-        return collision.unwrap() 
-    } else {
-        return Collision::Inside;
-    }
-}
\ No newline at end of file
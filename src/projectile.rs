@@ -0,0 +1,210 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+
+use crate::{
+    actions::{Action, ActionState, ReadsFixedPresses},
+    ascii::AsciiSheet,
+    gamestate::in_game,
+    health::{DamageEvent, DamageKind, Health},
+    map::TileMap,
+    menu::TEXT_COLOR,
+    player::{Facing, Player},
+    sound::SoundEffect,
+    stun::Stunned,
+};
+
+// This plugin flies projectiles until they hit a wall or something with `Health`, and
+// lets the player fire them while there is `Ammo` left, which is shown in the corner
+// of the screen. Enemies fire through `spawn_projectile` as well.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(
+                FixedUpdate, (
+                fire_projectile.in_set(ReadsFixedPresses),
+                move_projectiles,
+                // projectiles gone into walls can't hit anything anymore
+                apply_deferred,
+                projectile_hits,
+            ).chain().run_if(in_game))
+            .add_systems(Update, update_ammo_text.run_if(in_game));
+    }
+}
+
+pub const PROJECTILE_SIZE: f32 = 30.0;
+const PROJECTILE_LIFETIME: f32 = 2.0;
+// size of whatever a projectile can hit, players and enemies are the same size
+const TARGET_SIZE: f32 = 50.0;
+const PLAYER_PROJECTILE_SPEED: f32 = 400.0;
+const PLAYER_PROJECTILE_DAMAGE: i32 = 2;
+pub const STARTING_AMMO: u32 = 5;
+
+// Something flying through the level, only hurts the side its owner isn't on
+#[derive(Component)]
+pub struct Projectile {
+    velocity: Vec2,
+    lifetime: Timer,
+    owner: Entity,
+    damage: i32,
+}
+
+// Shots the player has left
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Ammo(pub u32);
+
+// Text showing the player's ammo, goes away with the level
+#[derive(Component)]
+pub struct AmmoText;
+
+// Line glyph closest to the direction, for things that point somewhere
+pub fn line_glyph(direction: Vec2) -> usize {
+    let eighth = (direction.y.atan2(direction.x) / std::f32::consts::FRAC_PI_4).round() as i32;
+    let glyph = match eighth.rem_euclid(4) {
+        0 => '-',
+        1 => '/',
+        2 => '|',
+        _ => '\\',
+    };
+    glyph as usize
+}
+
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    ascii: &Res<AsciiSheet>,
+    owner: Entity,
+    origin: Vec3,
+    velocity: Vec2,
+    damage: i32,
+    color: Color,
+) {
+    let mut sprite = TextureAtlasSprite::new(line_glyph(velocity));
+    sprite.color = color;
+    sprite.custom_size = Some(Vec2::splat(PROJECTILE_SIZE));
+
+    commands.spawn(SpriteSheetBundle {
+        sprite,
+        texture_atlas: ascii.0.clone(),
+        // above everything standing on the floor
+        transform: Transform::from_translation(origin.truncate().extend(900.0)),
+        ..default()
+    })
+    .insert(Projectile {
+        velocity,
+        lifetime: Timer::from_seconds(PROJECTILE_LIFETIME, TimerMode::Once),
+        owner,
+        damage,
+    })
+    .insert(Name::new("Projectile"));
+}
+
+fn fire_projectile(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    action_state: Res<ActionState>,
+    mut player_query: Query<(Entity, &Transform, &Facing, &mut Ammo, Has<Stunned>), With<Player>>,
+    mut sound_effects: EventWriter<SoundEffect>,
+) {
+    let (player_entity, transform, facing, mut ammo, stunned) = player_query.single_mut();
+    if stunned || !action_state.fixed_just_pressed(Action::Fire) || ammo.0 == 0 {
+        return;
+    }
+    ammo.0 -= 1;
+    spawn_projectile(
+        &mut commands,
+        &ascii,
+        player_entity,
+        transform.translation,
+        facing.vector() * PLAYER_PROJECTILE_SPEED,
+        PLAYER_PROJECTILE_DAMAGE,
+        Color::rgb(0.8, 0.8, 1.0),
+    );
+    sound_effects.send(SoundEffect::PlayerAttack);
+}
+
+fn update_ammo_text(
+    mut commands: Commands,
+    player_query: Query<&Ammo, With<Player>>,
+    mut text_query: Query<&mut Text, With<AmmoText>>,
+) {
+    let Ok(ammo) = player_query.get_single() else {
+        return;
+    };
+    let value = format!("Ammo: {}", ammo.0);
+    if let Ok(mut text) = text_query.get_single_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        return;
+    }
+    commands.spawn(
+        TextBundle::from_section(
+            value,
+            TextStyle {
+                font_size: 30.0,
+                color: TEXT_COLOR,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            // below the health, see `update_health_text`
+            top: Val::Px(45.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+    )
+    .insert(AmmoText)
+    .insert(Name::new("Ammo Text"));
+}
+
+// projectiles that run into a wall or out of time are gone
+fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    tile_map: Res<TileMap>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform)>,
+) {
+    for (entity, mut projectile, mut transform) in projectile_query.iter_mut() {
+        transform.translation += (projectile.velocity * time.delta_seconds()).extend(0.0);
+        if projectile.lifetime.tick(time.delta()).finished()
+            || tile_map.is_blocked(transform.translation, PROJECTILE_SIZE * 0.5)
+        {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn projectile_hits(
+    mut commands: Commands,
+    projectile_query: Query<(Entity, &Projectile, &Transform)>,
+    target_query: Query<(Entity, &Transform, Has<Player>), With<Health>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, projectile, transform) in projectile_query.iter() {
+        // players and enemies are the two sides, a dead owner was an enemy
+        let owner_is_player = target_query
+            .get(projectile.owner)
+            .is_ok_and(|(.., is_player)| is_player);
+        let target = target_query.iter().find(|(target, target_transform, is_player)| {
+            *target != projectile.owner
+                && *is_player != owner_is_player
+                && collide(
+                    transform.translation,
+                    Vec2::splat(PROJECTILE_SIZE * 0.5),
+                    target_transform.translation,
+                    Vec2::splat(TARGET_SIZE),
+                )
+                .is_some()
+        });
+        if let Some((target, ..)) = target {
+            damage_events.send(DamageEvent {
+                source: Some(projectile.owner),
+                target,
+                amount: projectile.damage,
+                kind: DamageKind::Ranged,
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
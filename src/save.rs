@@ -0,0 +1,406 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::{AiState, Guard},
+    ascii::AsciiSheet,
+    enemy::{spawn_enemy, Enemy, EnemyKind},
+    fov::Fov,
+    gamestate::GameState,
+    health::Health,
+    item::{spawn_item, Inventory, Item},
+    level::LevelManifest,
+    map::{load_level, open_door, spring_trap, Door, GameLevel, RunSeed, Tile, TileCollider, TileMap, Trap},
+    pause::PauseState,
+    player::Player,
+    projectile::Ammo,
+    summary::RunStats,
+};
+
+// This plugin writes the current run to disk from the pause menu and restores it
+// through the Continue button of the main menu. A run that ended has nothing left
+// to continue, so its save is deleted.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<SaveAndExit>()
+            .add_systems(Update, write_save.run_if(on_event::<SaveAndExit>()))
+            // the level is built from its file or seed first, then put back the way it was saved
+            .add_systems(
+                StateTransition,
+                (apply_deferred, restore_save)
+                    .chain()
+                    .after(load_level)
+                    .run_if(resource_exists::<LoadedSave>()),
+            )
+            .add_systems(OnEnter(GameState::GameOver), delete_save)
+            .add_systems(OnEnter(GameState::Victory), delete_save);
+    }
+}
+
+// Bumped whenever `SaveFile` changes, older saves can't be continued
+const SAVE_VERSION: u32 = 2;
+const SAVE_FILE_NAME: &str = "save.ron";
+
+// Sent to save the current run and leave it for the main menu
+#[derive(Event)]
+pub struct SaveAndExit;
+
+// Save that is being continued, removed again once the level is restored
+#[derive(Resource)]
+pub struct LoadedSave(pub SaveFile);
+
+// Only read to check the version before parsing the rest of the file
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    version: u32,
+    seed: u64,
+    level: SavedLevel,
+    stats: RunStats,
+    player: SavedPlayer,
+    enemies: Vec<SavedEnemy>,
+    items: Vec<SavedItem>,
+    // explored cells of the level row by row, see `Fov`
+    explored: Vec<bool>,
+    // grid cells of the doors opened and traps sprung, saves from before have none
+    #[serde(default)]
+    opened_doors: Vec<(i32, i32)>,
+    #[serde(default)]
+    sprung_traps: Vec<(i32, i32)>,
+}
+
+// Hand made levels are saved by their manifest id, so saves keep working when levels
+// are added to or reordered in the manifest
+#[derive(Serialize, Deserialize)]
+enum SavedLevel {
+    Level(String),
+    Generated(u32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    position: [f32; 3],
+    health: i32,
+    gold: u32,
+    potions: u32,
+    // saves from before ranged weapons have none
+    #[serde(default)]
+    ammo: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEnemy {
+    kind: EnemyKind,
+    position: [f32; 3],
+    health: i32,
+    state: AiState,
+    post: (i32, i32),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedItem {
+    item: Item,
+    position: [f32; 3],
+}
+
+// Why a save could not be continued, shown on the main menu
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Corrupt(String),
+    Version(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "the save file could not be read ({error})"),
+            SaveError::Corrupt(error) => write!(f, "the save file is damaged ({error})"),
+            SaveError::Version(version) => write!(
+                f,
+                "the save file is from another version of the game ({version}, expected {SAVE_VERSION})"
+            ),
+        }
+    }
+}
+
+// Saves live in the user's data directory, next to the game when there is none
+fn save_path() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("dungeon_delver"))
+        .unwrap_or_default()
+        .join(SAVE_FILE_NAME)
+}
+
+pub fn save_exists() -> bool {
+    save_path().is_file()
+}
+
+pub fn delete_save() {
+    let path = save_path();
+    if path.is_file() {
+        if let Err(error) = fs::remove_file(&path) {
+            warn!("Could not delete save {}: {error}", path.display());
+        }
+    }
+}
+
+// Reads the save, checking it still fits the current game before anything is loaded.
+// Returns the level to continue on along with the save.
+pub fn load_save(manifest: &LevelManifest) -> Result<(GameLevel, SaveFile), SaveError> {
+    let contents = fs::read_to_string(save_path()).map_err(SaveError::Io)?;
+    parse_save(&contents, manifest)
+}
+
+fn parse_save(contents: &str, manifest: &LevelManifest) -> Result<(GameLevel, SaveFile), SaveError> {
+    let header: SaveHeader =
+        ron::from_str(contents).map_err(|error| SaveError::Corrupt(error.to_string()))?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::Version(header.version));
+    }
+    let save: SaveFile =
+        ron::from_str(contents).map_err(|error| SaveError::Corrupt(error.to_string()))?;
+    let level = match &save.level {
+        SavedLevel::Level(id) => manifest
+            .find(id)
+            .ok_or_else(|| SaveError::Corrupt(format!("unknown level `{id}`")))?,
+        SavedLevel::Generated(depth) => GameLevel::Generated(*depth),
+    };
+    Ok((level, save))
+}
+
+impl SaveFile {
+    pub fn seed(&self) -> RunSeed {
+        RunSeed(self.seed)
+    }
+}
+
+fn write_save(
+    level: Res<State<GameState>>,
+    run_seed: Res<RunSeed>,
+    run_stats: Res<RunStats>,
+    fov: Res<Fov>,
+    manifest: Res<LevelManifest>,
+    player_query: Query<(&Transform, &Health, &Inventory, &Ammo), With<Player>>,
+    enemy_query: Query<(&Transform, &Enemy, &Health, &AiState, &Guard)>,
+    item_query: Query<(&Transform, &Item)>,
+    door_query: Query<&Transform, (With<Door>, Without<TileCollider>)>,
+    trap_query: Query<(&Transform, &Trap)>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+) {
+    let GameState::Game(level) = *level.get() else {
+        return;
+    };
+    let level = match level {
+        GameLevel::Level(_) => {
+            SavedLevel::Level(manifest.id(level).expect("Level missing from the manifest").to_string())
+        }
+        GameLevel::Generated(depth) => SavedLevel::Generated(depth),
+    };
+    let (player_transform, player_health, inventory, ammo) = player_query.single();
+    let save = SaveFile {
+        version: SAVE_VERSION,
+        seed: run_seed.0,
+        level,
+        stats: run_stats.clone(),
+        player: SavedPlayer {
+            position: player_transform.translation.to_array(),
+            health: player_health.health,
+            gold: inventory.gold,
+            potions: inventory.potions,
+            ammo: ammo.0,
+        },
+        enemies: enemy_query
+            .iter()
+            .map(|(transform, enemy, health, state, guard)| SavedEnemy {
+                kind: enemy.kind,
+                position: transform.translation.to_array(),
+                health: health.health,
+                state: *state,
+                post: (guard.post.x, guard.post.y),
+            })
+            .collect(),
+        items: item_query
+            .iter()
+            .map(|(transform, item)| SavedItem {
+                item: *item,
+                position: transform.translation.to_array(),
+            })
+            .collect(),
+        explored: fov.explored().to_vec(),
+        opened_doors: door_query
+            .iter()
+            .map(|transform| TileMap::world_to_grid(transform.translation).into())
+            .collect(),
+        sprung_traps: trap_query
+            .iter()
+            .filter(|(_, trap)| !trap.is_armed())
+            .map(|(transform, _)| TileMap::world_to_grid(transform.translation).into())
+            .collect(),
+    };
+
+    let path = save_path();
+    let written = ron::to_string(&save)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        .and_then(|contents| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, contents)
+        });
+    match written {
+        Ok(()) => {
+            info!("Saved the run to {}", path.display());
+            pause_state.set(PauseState::Running);
+            game_state.set(GameState::Menu);
+        }
+        // stay in the paused run rather than losing it
+        Err(error) => error!("Could not save the run to {}: {error}", path.display()),
+    }
+}
+
+fn restore_save(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    loaded_save: Res<LoadedSave>,
+    mut fov: ResMut<Fov>,
+    mut player_query: Query<(&mut Transform, &mut Health, &mut Inventory, &mut Ammo), With<Player>>,
+    enemy_query: Query<Entity, With<Enemy>>,
+    item_query: Query<Entity, With<Item>>,
+    mut tile_map: ResMut<TileMap>,
+    mut door_query: Query<
+        (Entity, &Transform, &mut TextureAtlasSprite),
+        (With<Door>, With<TileCollider>, Without<Trap>, Without<Player>),
+    >,
+    mut trap_query: Query<(&Transform, &mut Trap, &mut Tile, &mut TextureAtlasSprite), Without<Player>>,
+) {
+    let save = &loaded_save.0;
+    let (mut transform, mut health, mut inventory, mut ammo) = player_query.single_mut();
+    transform.translation = Vec3::from_array(save.player.position);
+    health.health = save.player.health;
+    *inventory = Inventory {
+        gold: save.player.gold,
+        potions: save.player.potions,
+    };
+    *ammo = Ammo(save.player.ammo);
+
+    // the fresh level has everything of its map file again, swap in what was left when saving
+    for entity in enemy_query.iter().chain(item_query.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    for enemy in save.enemies.iter() {
+        let entity = spawn_enemy(&mut commands, &ascii, enemy.kind, Vec3::from_array(enemy.position));
+        commands
+            .entity(entity)
+            .insert(Health {
+                health: enemy.health,
+            })
+            .insert(enemy.state)
+            .insert(Guard::new(IVec2::new(enemy.post.0, enemy.post.1)));
+    }
+    for item in save.items.iter() {
+        spawn_item(&mut commands, &ascii, item.item, Vec3::from_array(item.position));
+    }
+
+    for (entity, transform, mut sprite) in door_query.iter_mut() {
+        let cell: (i32, i32) = TileMap::world_to_grid(transform.translation).into();
+        if save.opened_doors.contains(&cell) {
+            open_door(&mut commands, &mut tile_map, entity, transform, &mut sprite);
+        }
+    }
+    for (transform, mut trap, mut tile, mut sprite) in trap_query.iter_mut() {
+        let cell: (i32, i32) = TileMap::world_to_grid(transform.translation).into();
+        if save.sprung_traps.contains(&cell) {
+            spring_trap(&mut trap, &mut tile, &mut sprite);
+        }
+    }
+
+    fov.restore_explored(save.explored.clone());
+    commands.insert_resource(save.stats.clone());
+    commands.remove_resource::<LoadedSave>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::read_level_manifest;
+
+    fn save_file(level: SavedLevel) -> SaveFile {
+        SaveFile {
+            version: SAVE_VERSION,
+            seed: 7,
+            level,
+            stats: RunStats::default(),
+            player: SavedPlayer {
+                position: [50.0, -50.0, 900.0],
+                health: 10,
+                gold: 1,
+                potions: 2,
+                ammo: 3,
+            },
+            enemies: Vec::new(),
+            items: Vec::new(),
+            explored: Vec::new(),
+            opened_doors: vec![(4, 2)],
+            sprung_traps: vec![(6, 3)],
+        }
+    }
+
+    #[test]
+    fn saved_run_parses_back() {
+        let manifest = read_level_manifest();
+        let contents = ron::to_string(&save_file(SavedLevel::Generated(2))).unwrap();
+        let (level, save) = parse_save(&contents, &manifest).unwrap();
+        assert_eq!(level, GameLevel::Generated(2));
+        assert_eq!(save.player.ammo, 3);
+        assert_eq!(save.opened_doors, vec![(4, 2)]);
+        assert_eq!(save.sprung_traps, vec![(6, 3)]);
+    }
+
+    #[test]
+    fn other_version_is_an_error() {
+        let manifest = read_level_manifest();
+        let mut save = save_file(SavedLevel::Level("level_1".to_string()));
+        save.version = SAVE_VERSION + 1;
+        let contents = ron::to_string(&save).unwrap();
+        let error = parse_save(&contents, &manifest).err().unwrap();
+        assert!(matches!(error, SaveError::Version(version) if version == SAVE_VERSION + 1));
+    }
+
+    #[test]
+    fn corrupt_save_is_an_error() {
+        let manifest = read_level_manifest();
+        let contents = ron::to_string(&save_file(SavedLevel::Level("level_1".to_string()))).unwrap();
+        for broken in ["", "not a save", &contents[..contents.len() / 2]] {
+            let error = parse_save(broken, &manifest).err().unwrap();
+            assert!(matches!(error, SaveError::Corrupt(_)), "{broken:?} gave {error}");
+        }
+    }
+
+    #[test]
+    fn saved_level_id_is_looked_up_in_the_manifest() {
+        let manifest = read_level_manifest();
+        let contents = ron::to_string(&save_file(SavedLevel::Level("level_2".to_string()))).unwrap();
+        let (level, _) = parse_save(&contents, &manifest).unwrap();
+        assert_eq!(level, manifest.find("level_2").unwrap());
+        assert_eq!(manifest.id(level), Some("level_2"));
+    }
+
+    #[test]
+    fn unknown_level_is_an_error() {
+        let manifest = read_level_manifest();
+        let contents = ron::to_string(&save_file(SavedLevel::Level("no_such_level".to_string()))).unwrap();
+        let error = parse_save(&contents, &manifest).err().unwrap();
+        assert!(matches!(error, SaveError::Corrupt(_)));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use bevy::{prelude::*, window::{PresentMode, PrimaryWindow}};
+use serde::{
+    de::{Error as _, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+
+use crate::{actions::KeyBindings, menu::MenuState};
+
+// This plugin loads the settings picked in the menu, key bindings included, from the
+// user's config directory at startup and writes them back whenever a settings screen
+// is left
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(PreStartup, load_settings)
+            .add_systems(
+                Update,
+                apply_display_quality.run_if(resource_changed::<DisplayQuality>()),
+            )
+            .add_systems(OnExit(MenuState::SettingsDisplay), save_settings)
+            .add_systems(OnExit(MenuState::SettingsSound), save_settings)
+            .add_systems(OnExit(MenuState::SettingsControls), save_settings);
+    }
+}
+
+const SETTINGS_FILE_NAME: &str = "settings.ron";
+
+// One of the two settings that can be set through the menu. It will be a resource in the app
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum DisplayQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl DisplayQuality {
+    // window size in logical pixels
+    fn resolution(self) -> (f32, f32) {
+        match self {
+            DisplayQuality::Low => (960.0, 540.0),
+            DisplayQuality::Medium => (1280.0, 720.0),
+            DisplayQuality::High => (1600.0, 900.0),
+        }
+    }
+
+    // low quality ignores high dpi screens and renders one pixel per logical pixel
+    fn scale_factor_override(self) -> Option<f64> {
+        match self {
+            DisplayQuality::Low => Some(1.0),
+            DisplayQuality::Medium | DisplayQuality::High => None,
+        }
+    }
+
+    fn present_mode(self) -> PresentMode {
+        match self {
+            DisplayQuality::Low => PresentMode::AutoNoVsync,
+            DisplayQuality::Medium | DisplayQuality::High => PresentMode::AutoVsync,
+        }
+    }
+
+    fn msaa(self) -> Msaa {
+        match self {
+            DisplayQuality::Low => Msaa::Off,
+            DisplayQuality::Medium => Msaa::Sample2,
+            DisplayQuality::High => Msaa::Sample4,
+        }
+    }
+
+    // lighting and particle effects, off on low quality
+    pub fn effects(self) -> bool {
+        self != DisplayQuality::Low
+    }
+
+    // how many tiles get their colour updated per frame after the player's view changed
+    pub fn fov_tiles_per_frame(self) -> usize {
+        match self {
+            DisplayQuality::Low => 150,
+            DisplayQuality::Medium => 500,
+            DisplayQuality::High => usize::MAX,
+        }
+    }
+}
+
+// One of the two settings that can be set through the menu. It will be a resource in the app
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Volume(pub u32);
+
+impl Volume {
+    // highest volume that can be picked in the menu
+    const MAX: u32 = 9;
+
+    // loudness from silent at 0.0 to full at 1.0
+    pub fn level(self) -> f32 {
+        self.0.min(Volume::MAX) as f32 / Volume::MAX as f32
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Volume(7)
+    }
+}
+
+// Layout of the settings file
+#[derive(Serialize, Default)]
+struct SettingsFile {
+    display_quality: DisplayQuality,
+    volume: Volume,
+    key_bindings: KeyBindings,
+}
+
+// Names of the fields of `SettingsFile`
+const SETTINGS_FIELDS: &[&str] = &["display_quality", "volume", "key_bindings"];
+
+fn settings_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join("dungeon_delver"))
+        .unwrap_or_default()
+        .join(SETTINGS_FILE_NAME)
+}
+
+fn load_settings(mut commands: Commands) {
+    let path = settings_path();
+    let settings = match fs::read_to_string(&path) {
+        Ok(contents) => parse_settings(&contents),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => SettingsFile::default(),
+        Err(error) => {
+            warn!("Could not read settings {}, using the defaults: {error}", path.display());
+            SettingsFile::default()
+        }
+    };
+    commands.insert_resource(settings.display_quality);
+    commands.insert_resource(settings.volume);
+    commands.insert_resource(settings.key_bindings.with_defaults());
+}
+
+// The file is read in one go straight into the settings. Missing settings keep their
+// default and unknown ones are skipped, a bad value stops the reading there so the
+// settings after it keep their default too.
+fn parse_settings(contents: &str) -> SettingsFile {
+    let mut settings = SettingsFile::default();
+    let parsed = ron::Deserializer::from_str(contents)
+        .map_err(ron::Error::from)
+        .and_then(|mut deserializer| {
+            deserializer.deserialize_struct(
+                "SettingsFile",
+                SETTINGS_FIELDS,
+                SettingsVisitor(&mut settings),
+            )?;
+            deserializer.end()
+        });
+    if let Err(error) = parsed {
+        warn!("Settings file is malformed, the rest of it uses the defaults: {error}");
+    }
+    settings
+}
+
+// Fills in the settings as their fields come up in the file
+struct SettingsVisitor<'a>(&'a mut SettingsFile);
+
+impl<'de> Visitor<'de> for SettingsVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a struct of settings")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let settings = self.0;
+        while let Some(FieldName(name)) = map.next_key()? {
+            let read = match name.as_str() {
+                "display_quality" => map.next_value().map(|value| settings.display_quality = value),
+                "volume" => map.next_value().map(|value| settings.volume = value),
+                "key_bindings" => map.next_value().map(|value| settings.key_bindings = value),
+                _ => {
+                    warn!("Ignoring unknown setting `{name}`");
+                    map.next_value::<IgnoredAny>().map(|_| ())
+                }
+            };
+            read.map_err(|error| A::Error::custom(format_args!("setting `{name}` is invalid ({error})")))?;
+        }
+        Ok(())
+    }
+}
+
+// Field names of RON structs are identifiers rather than strings
+struct FieldName(String);
+
+impl<'de> Deserialize<'de> for FieldName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldNameVisitor;
+
+        impl<'de> Visitor<'de> for FieldNameVisitor {
+            type Value = FieldName;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_str<E>(self, name: &str) -> Result<FieldName, E> {
+                Ok(FieldName(name.to_string()))
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldNameVisitor)
+    }
+}
+
+// Runs whenever the display quality is picked in the menu, and once at startup
+fn apply_display_quality(
+    mut commands: Commands,
+    display_quality: Res<DisplayQuality>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let quality = *display_quality;
+    commands.insert_resource(quality.msaa());
+    let Ok(mut window) = window_query.get_single_mut() else {
+        return;
+    };
+    let (width, height) = quality.resolution();
+    window.resolution.set(width, height);
+    window.resolution.set_scale_factor_override(quality.scale_factor_override());
+    window.present_mode = quality.present_mode();
+}
+
+fn save_settings(
+    display_quality: Res<DisplayQuality>,
+    volume: Res<Volume>,
+    key_bindings: Res<KeyBindings>,
+) {
+    let settings = SettingsFile {
+        display_quality: *display_quality,
+        volume: *volume,
+        key_bindings: key_bindings.clone(),
+    };
+    let path = settings_path();
+    let written = ron::ser::to_string_pretty(&settings, ron::ser::PrettyConfig::default())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        .and_then(|contents| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, contents)
+        });
+    if let Err(error) = written {
+        warn!("Could not save settings to {}: {error}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{Action, Preset};
+
+    #[test]
+    fn saved_settings_read_back() {
+        let mut key_bindings = KeyBindings::preset(Preset::Vi);
+        key_bindings.bind(Action::Fire, KeyCode::G);
+        let saved = SettingsFile {
+            display_quality: DisplayQuality::High,
+            volume: Volume(3),
+            key_bindings: key_bindings.clone(),
+        };
+        let contents = ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::default()).unwrap();
+        let settings = parse_settings(&contents);
+        assert_eq!(settings.display_quality, DisplayQuality::High);
+        assert_eq!(settings.volume, Volume(3));
+        assert_eq!(settings.key_bindings, key_bindings);
+    }
+
+    #[test]
+    fn unknown_settings_are_skipped() {
+        let settings = parse_settings("(fullscreen: true, volume: 2, colours: [Red, Green], display_quality: Low)");
+        assert_eq!(settings.volume, Volume(2));
+        assert_eq!(settings.display_quality, DisplayQuality::Low);
+    }
+
+    #[test]
+    fn missing_settings_use_the_defaults() {
+        let settings = parse_settings("(volume: 4)");
+        assert_eq!(settings.volume, Volume(4));
+        assert_eq!(settings.display_quality, DisplayQuality::default());
+        assert_eq!(settings.key_bindings, KeyBindings::default());
+
+        let settings = parse_settings("()");
+        assert_eq!(settings.volume, Volume::default());
+    }
+
+    #[test]
+    fn bad_value_keeps_the_settings_before_it() {
+        let settings = parse_settings("(display_quality: High, volume: \"loud\", key_bindings: {})");
+        assert_eq!(settings.display_quality, DisplayQuality::High);
+        assert_eq!(settings.volume, Volume::default());
+        assert_eq!(settings.key_bindings, KeyBindings::default());
+    }
+
+    #[test]
+    fn malformed_file_uses_the_defaults() {
+        for contents in ["", "not settings", "(volume: 4", "[1, 2, 3]"] {
+            let settings = parse_settings(contents);
+            assert_eq!(settings.display_quality, DisplayQuality::default(), "{contents:?}");
+            assert_eq!(settings.key_bindings, KeyBindings::default(), "{contents:?}");
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, env};
+
+use bevy::{audio::Volume as PlaybackVolume, prelude::*};
+
+use crate::{gamestate::GameState, level::LevelManifest, map::GameLevel, settings::Volume};
+
+// This plugin plays the music of the current level and the sound effects gameplay
+// systems ask for through `SoundEffect` events. Everything is scaled by the `Volume`
+// setting, and nothing is played while `Muted`.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Muted::from_env())
+            .add_event::<SoundEffect>()
+            // loaded even while muted, so unmuting mid game finds them
+            .add_systems(Startup, load_sound_effects)
+            .add_systems(
+                StateTransition,
+                play_level_music
+                    .after(apply_state_transition::<GameState>)
+                    .run_if(state_changed::<GameState>().or_else(resource_changed::<Muted>())),
+            )
+            .add_systems(
+                Update, (
+                apply_volume.run_if(resource_changed::<Volume>().or_else(resource_changed::<Muted>())),
+                play_sound_effects,
+            ).run_if(not_muted));
+    }
+}
+
+// music plays a bit quieter than the sound effects
+const MUSIC_VOLUME: f32 = 0.5;
+// generated levels have no manifest entry to name their music
+const GENERATED_LEVEL_MUSIC: &str = "music/depths.wav";
+
+// Something that happened in the game and has a sound to it
+#[derive(Event, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEffect {
+    PlayerAttack,
+    EnemyHit,
+    PlayerHurt,
+    Death,
+    LevelExit,
+}
+
+impl SoundEffect {
+    const ALL: [SoundEffect; 5] = [
+        SoundEffect::PlayerAttack,
+        SoundEffect::EnemyHit,
+        SoundEffect::PlayerHurt,
+        SoundEffect::Death,
+        SoundEffect::LevelExit,
+    ];
+
+    fn path(self) -> &'static str {
+        match self {
+            SoundEffect::PlayerAttack => "sounds/attack.wav",
+            SoundEffect::EnemyHit => "sounds/enemy_hit.wav",
+            SoundEffect::PlayerHurt => "sounds/player_hurt.wav",
+            SoundEffect::Death => "sounds/death.wav",
+            SoundEffect::LevelExit => "sounds/level_exit.wav",
+        }
+    }
+}
+
+// Turns all sound off, set `DELVER_MUTE=1` or insert `Muted(true)` to run without audio.
+// It can be changed while the game runs, the level's music stops and starts with it.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Muted(pub bool);
+
+impl Muted {
+    fn from_env() -> Self {
+        Muted(env::var("DELVER_MUTE").is_ok_and(|value| value != "0"))
+    }
+}
+
+pub fn not_muted(muted: Res<Muted>) -> bool {
+    !muted.0
+}
+
+// Sound effects are loaded up front so the first one doesn't play late
+#[derive(Resource)]
+struct SoundEffects(HashMap<SoundEffect, Handle<AudioSource>>);
+
+// The track playing in the background
+#[derive(Component)]
+struct Music {
+    track: String,
+}
+
+fn load_sound_effects(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = SoundEffect::ALL
+        .into_iter()
+        .map(|effect| (effect, asset_server.load(effect.path())))
+        .collect();
+    commands.insert_resource(SoundEffects(handles));
+}
+
+// Keeps the current track going when the next level uses the same one, and stops it
+// while muted
+fn play_level_music(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state: Res<State<GameState>>,
+    manifest: Res<LevelManifest>,
+    muted: Res<Muted>,
+    music_query: Query<(Entity, &Music)>,
+) {
+    let track = match *state.get() {
+        _ if muted.0 => None,
+        GameState::Game(level @ GameLevel::Level(_)) => {
+            manifest.get(level).and_then(|info| info.music.clone())
+        }
+        GameState::Game(GameLevel::Generated(_)) => Some(GENERATED_LEVEL_MUSIC.to_string()),
+        _ => None,
+    };
+
+    for (entity, music) in music_query.iter() {
+        if Some(&music.track) == track.as_ref() {
+            return;
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if let Some(track) = track {
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(&track),
+                settings: PlaybackSettings::LOOP.with_volume(PlaybackVolume::new_relative(MUSIC_VOLUME)),
+            },
+            Music { track },
+        ));
+    }
+}
+
+// New sounds pick up the global volume, the music that is already playing is turned
+// to it by hand. Changes made while muted are applied once unmuted.
+fn apply_volume(
+    mut commands: Commands,
+    volume: Res<Volume>,
+    music_query: Query<&AudioSink, With<Music>>,
+) {
+    commands.insert_resource(GlobalVolume::new(volume.level()));
+    for sink in music_query.iter() {
+        sink.set_volume(volume.level() * MUSIC_VOLUME);
+    }
+}
+
+fn play_sound_effects(
+    mut commands: Commands,
+    sound_effects: Res<SoundEffects>,
+    mut events: EventReader<SoundEffect>,
+) {
+    for effect in events.read() {
+        commands.spawn(AudioBundle {
+            source: sound_effects.0[effect].clone(),
+            settings: PlaybackSettings::DESPAWN,
+        });
+    }
+}
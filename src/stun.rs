@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use crate::{
+    enemy::Enemy,
+    gamestate::in_game,
+    health::{apply_damage, DamageEvent, Health, Invulnerable},
+    player::Player,
+};
+
+// This plugin stuns enemies and the player for a moment whenever they get hurt.
+// Stunned enemies and players don't move or attack, and their sprite flashes until
+// it wears off. The player gets over it quicker so a hit doesn't feel unfair.
+pub struct StunPlugin;
+
+impl Plugin for StunPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(
+                FixedUpdate, (
+                stun_on_hit.after(apply_damage),
+                update_stuns,
+            ).chain().run_if(in_game));
+    }
+}
+
+const ENEMY_STUN_SECONDS: f32 = 0.6;
+const PLAYER_STUN_SECONDS: f32 = 0.25;
+const FLASH_SECONDS: f32 = 0.1;
+const FLASH_COLOR: Color = Color::WHITE;
+
+// Keeps an entity from moving and attacking until the timer runs out
+#[derive(Component)]
+pub struct Stunned {
+    timer: Timer,
+    // the sprite's own colour, put back once the stun is over
+    color: Option<Color>,
+}
+
+impl Stunned {
+    pub fn new(seconds: f32) -> Self {
+        Stunned {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+            color: None,
+        }
+    }
+}
+
+// being hit again while stunned starts the stun over
+fn stun_on_hit(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut target_query: Query<
+        (&Health, Option<&mut Stunned>, Has<Player>, Has<Invulnerable>),
+        Or<(With<Enemy>, With<Player>)>,
+    >,
+) {
+    for damage in damage_events.read() {
+        let Ok((health, stunned, is_player, invulnerable)) = target_query.get_mut(damage.target) else {
+            continue;
+        };
+        // dead enemies are despawned and a dead player ends the run, nothing to stun
+        if health.health <= 0 || invulnerable {
+            continue;
+        }
+        let seconds = if is_player { PLAYER_STUN_SECONDS } else { ENEMY_STUN_SECONDS };
+        match stunned {
+            Some(mut stunned) => stunned.timer.reset(),
+            None => {
+                commands.entity(damage.target).insert(Stunned::new(seconds));
+            }
+        }
+    }
+}
+
+fn update_stuns(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut stunned_query: Query<(Entity, &mut Stunned, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut stunned, mut sprite) in stunned_query.iter_mut() {
+        let color = *stunned.color.get_or_insert(sprite.color);
+        if stunned.timer.tick(time.delta()).finished() {
+            sprite.color = color;
+            commands.entity(entity).remove::<Stunned>();
+            continue;
+        }
+        let flashes = (stunned.timer.elapsed_secs() / FLASH_SECONDS) as u32;
+        sprite.color = if flashes.is_multiple_of(2) { FLASH_COLOR } else { color };
+    }
+}
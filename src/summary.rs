@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gamestate::*,
+    level::LevelManifest,
+    map::Entrance,
+    menu::{button_system, NORMAL_BUTTON, TEXT_COLOR},
+};
+
+// This plugin keeps track of how the run went and shows it on the game over and
+// victory screens once the run ends
+pub struct SummaryPlugin;
+
+impl Plugin for SummaryPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<RunStats>()
+            .add_systems(Update, track_time_played.run_if(in_game))
+            .add_systems(OnEnter(GameState::GameOver), game_over_setup)
+            .add_systems(OnExit(GameState::GameOver), despawn_screen::<OnSummaryScreen>)
+            .add_systems(OnEnter(GameState::Victory), victory_setup)
+            .add_systems(OnExit(GameState::Victory), despawn_screen::<OnSummaryScreen>)
+            .add_systems(
+                Update,
+                (summary_action, button_system)
+                    .run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Victory))),
+            );
+    }
+}
+
+// Numbers of the current run, reset whenever a new run starts
+#[derive(Resource, Default, Debug, Clone, Serialize, Deserialize)]
+pub struct RunStats {
+    // deepest level entered, see `LevelManifest::depth`
+    pub depth: u32,
+    pub kills: u32,
+    pub damage_dealt: i32,
+    // from killing enemies, see `EnemyKind::experience`
+    #[serde(default)]
+    pub experience: u32,
+    pub time_played: Duration,
+}
+
+// Tag component used to tag entities added on the game over and victory screens
+#[derive(Component)]
+struct OnSummaryScreen;
+
+// All actions that can be triggered from a button click
+#[derive(Component)]
+enum SummaryButtonAction {
+    Retry,
+    MainMenu,
+}
+
+fn track_time_played(time: Res<Time>, mut run_stats: ResMut<RunStats>) {
+    run_stats.time_played += time.delta();
+}
+
+fn game_over_setup(commands: Commands, run_stats: Res<RunStats>) {
+    summary_screen_setup(commands, "You Died", Color::MAROON, &run_stats);
+}
+
+fn victory_setup(commands: Commands, run_stats: Res<RunStats>) {
+    summary_screen_setup(commands, "Victory!", Color::DARK_GREEN, &run_stats);
+}
+
+fn summary_screen_setup(mut commands: Commands, title: &str, background: Color, run_stats: &RunStats) {
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    let stat_text_style = TextStyle {
+        font_size: 30.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+
+    let seconds = run_stats.time_played.as_secs();
+    let stats = [
+        format!("Depth reached: {}", run_stats.depth),
+        format!("Enemies killed: {}", run_stats.kills),
+        format!("Damage dealt: {}", run_stats.damage_dealt),
+        format!("Experience: {}", run_stats.experience),
+        format!("Time played: {}:{:02}", seconds / 60, seconds % 60),
+    ];
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnSummaryScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: background.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            title,
+                            TextStyle {
+                                font_size: 80.0,
+                                color: TEXT_COLOR,
+                                ..default()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(50.0)),
+                            ..default()
+                        }),
+                    );
+
+                    // Display how the run went, one line per stat
+                    for stat in stats {
+                        parent.spawn(
+                            TextBundle::from_section(stat, stat_text_style.clone()).with_style(Style {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            }),
+                        );
+                    }
+
+                    for (action, text) in [
+                        (SummaryButtonAction::Retry, "Retry"),
+                        (SummaryButtonAction::MainMenu, "Main Menu"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(text, button_text_style.clone()));
+                            });
+                    }
+                });
+        });
+}
+
+fn summary_action(
+    interaction_query: Query<
+        (&Interaction, &SummaryButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut game_state: ResMut<NextState<GameState>>,
+    manifest: Res<LevelManifest>,
+    mut commands: Commands,
+) {
+    for (interaction, summary_button_action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            match summary_button_action {
+                // same seed, so the generated levels of the run come back the same
+                SummaryButtonAction::Retry => {
+                    commands.insert_resource(Entrance::default());
+                    commands.insert_resource(RunStats::default());
+                    game_state.set(GameState::Game(manifest.start));
+                }
+                SummaryButtonAction::MainMenu => game_state.set(GameState::Menu),
+            }
+        }
+    }
+}